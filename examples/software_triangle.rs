@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate graphic_backend;
+extern crate image;
+
+use std::path::Path;
+use image::GenericImage;
+
+use graphic_backend::*;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec3 position;
+    in vec2 coord;
+    out vec2 coord0;
+    void main() {
+        coord0 = coord;
+        gl_Position = vec4(position, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    in vec2 coord0;
+    out vec4 out_color;
+    uniform sampler2D tex;
+    void main() {
+        out_color = texture2D(tex, coord0);
+    }
+"#;
+
+fn main() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Software::new(640, 480);
+    facade.shader(VS_SRC,
+                  FS_SRC,
+                  |attrs, _| {
+                      let p = attrs.vec3("position");
+                      ([p[0], p[1], p[2], 1.0], attrs.vec2("coord").to_vec())
+                  },
+                  |coord0, env| env.texture("tex", coord0[0], coord0[1]));
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vertexes = Vec3(vec![-1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0]);
+    let coords = Vec2(vec![-1.0, -1.0, 0.0, 1.0, 1.0, -1.0]);
+    let vb = facade.vertex_buffer()
+        .add_input("position", vertexes)
+        .add_input("coord", coords)
+        .build()
+        .unwrap();
+
+    let img = image::open(&Path::new("resource/denim.png")).unwrap();
+    let (width, height) = img.dimensions();
+    let tex = facade.texture2d(ColorFormat::RGBA, width, height, img.to_rgba().into_raw())
+        .unwrap();
+
+    let uniforms = uniforms! {
+        tex: tex.as_uniform()
+    };
+
+    let mut frame = facade.frame();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &Default::default()).unwrap();
+    frame.finish().unwrap();
+
+    facade.screenshot("software_triangle.png").unwrap();
+}
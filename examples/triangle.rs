@@ -33,19 +33,21 @@ static FS_SRC: &'static str = r#"
 fn main() {
     use graphic_backend::InputBuffer::*;
 
-    let mut facade = OpenGL::new();
+    let mut facade = OpenGL::new().unwrap();
     let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
     let vertexes = Vec3(vec![-1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0]);
     let coords = Vec2(vec![-1.0, -1.0, 0.0, 1.0, 1.0, -1.0]);
     let vb = facade.vertex_buffer()
         .add_input("position", vertexes)
         .add_input("coord", coords)
-        .build(&program);
+        .build()
+        .unwrap();
     // let mut uniforms = Uniforms::new();
 
     let img = image::open(&Path::new("resource/denim.png")).unwrap();
     let (width, height) = img.dimensions();
-    let tex = facade.texture2d(ColorFormat::RGBA, width, height, img.to_rgba().into_raw());
+    let tex = facade.texture2d(ColorFormat::RGBA, width, height, img.to_rgba().into_raw())
+        .unwrap();
     // uniforms.add_uniform("tex", tex.as_uniform());
 
     let uniforms = uniforms! {
@@ -54,7 +56,8 @@ fn main() {
 
     loop {
         let mut frame = facade.frame();
-        frame.draw(&program, DrawType::Triangles, &vb, &uniforms);
-        frame.finish();
+        frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+        frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &Default::default()).unwrap();
+        frame.finish().unwrap();
     }
 }
@@ -1,7 +1,18 @@
 
 use super::Event;
+use super::error::BackendError;
+use super::parameters::DrawParameters;
+use super::std140::{self, Std140};
 
+use image::RgbaImage;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
 use std::mem;
+use std::ops::Range;
+use std::path::Path;
+use std::slice;
 
 pub type BProgram = Box<Program>;
 pub type BBuffer = Box<Buffer>;
@@ -9,18 +20,34 @@ pub type BVertexBuffer = Box<VertexBuffer>;
 pub type BVertexBufferBuilder = Box<VertexBufferBuilder>;
 pub type BFrame = Box<Frame>;
 pub type BTexture2D = Box<Texture2D>;
+pub type BUniformBuffer = Box<UniformBuffer>;
+pub type BFramebuffer = Box<Framebuffer>;
 
 pub trait Facade {
-    type VertexBufferBuilder;
+    type VertexBufferBuilder: VertexBufferBuilder;
     fn program(&self,
                vssrc: &str,
                fssrc: &str,
                gssrc: Option<&str>,
                out: &str)
-               -> Result<BProgram, String>;
+               -> Result<BProgram, BackendError>;
     fn vertex_buffer(&self) -> Self::VertexBufferBuilder;
     fn frame(&self) -> BFrame;
-    fn texture2d(&self, format: ColorFormat, width: u32, height: u32, data: Vec<u8>) -> BTexture2D;
+    fn texture2d(&self,
+                 format: ColorFormat,
+                 width: u32,
+                 height: u32,
+                 data: Vec<u8>)
+                 -> Result<BTexture2D, BackendError>;
+    /// Uploads `data` into a new buffer for a std140 uniform block.
+    fn uniform_buffer<T: Std140>(&self, data: &T) -> Result<BUniformBuffer, BackendError>;
+    /// A render target drawing into `colors`, in the order of the fragment
+    /// outputs, which must all be the same size.
+    fn framebuffer(&self,
+                   colors: &[&BTexture2D],
+                   depth: DepthStencil)
+                   -> Result<BFramebuffer, BackendError>;
+    fn screenshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()>;
 }
 
 macro_rules! impl_facade {
@@ -30,6 +57,8 @@ macro_rules! impl_facade {
         Program => $program:ident,
         VertexBufferBuilder => $vbb:ident,
         Texture2D => $tex2d:ident,
+        UniformBuffer => $ubo:ident,
+        Framebuffer => $fb:ident,
     }) => (
         impl Facade for $name {
             type VertexBufferBuilder = $vbb;
@@ -38,17 +67,43 @@ macro_rules! impl_facade {
                        fssrc: &str,
                        gssrc: Option<&str>,
                        out: &str)
-                       -> Result<BProgram, String> {
-                Ok(Box::new(try!($program::from_source(vssrc, fssrc, gssrc, out))))
+                       -> Result<BProgram, BackendError> {
+                Ok(Box::new(try!($program::from_source(&self.$selfcontext,
+                                                        vssrc,
+                                                        fssrc,
+                                                        gssrc,
+                                                        out))))
             }
             fn vertex_buffer(&self) -> $vbb {
-                $vbb::new()
+                $vbb::new(&self.$selfcontext)
             }
             fn frame(&self) -> BFrame {
                 Box::new($frame::new(self.$selfcontext.clone()))
             }
-            fn texture2d(&self, format: ColorFormat, width: u32, height: u32, data: Vec<u8>) -> BTexture2D {
-                Box::new($tex2d::new(format, width, height, data))
+            fn texture2d(&self,
+                         format: ColorFormat,
+                         width: u32,
+                         height: u32,
+                         data: Vec<u8>)
+                         -> Result<BTexture2D, BackendError> {
+                Ok(Box::new(try!($tex2d::new(&self.$selfcontext, format, width, height, data))))
+            }
+            fn uniform_buffer<T: $crate::std140::Std140>(&self, data: &T)
+                                                         -> Result<BUniformBuffer, BackendError> {
+                Ok(Box::new(try!($ubo::new(&self.$selfcontext,
+                                           &$crate::std140::to_bytes(data)))))
+            }
+            fn framebuffer(&self,
+                           colors: &[&BTexture2D],
+                           depth: DepthStencil)
+                           -> Result<BFramebuffer, BackendError> {
+                Ok(Box::new(try!($fb::new(&self.$selfcontext, colors, depth))))
+            }
+            fn screenshot<P: AsRef<::std::path::Path>>(&self, path: P) -> ::std::io::Result<()> {
+                self.$selfcontext
+                    .read_pixels()
+                    .save(path)
+                    .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))
             }
         }
     );
@@ -56,7 +111,10 @@ macro_rules! impl_facade {
 
 pub trait Context {
     fn get_events(&self) -> Vec<Event>;
-    fn finish(&self);
+    fn dimensions(&self) -> (u32, u32);
+    /// Pixels of the last finished frame, top row first.
+    fn read_pixels(&self) -> RgbaImage;
+    fn finish(&self) -> Result<(), BackendError>;
 }
 
 pub trait Frame {
@@ -64,88 +122,878 @@ pub trait Frame {
             program: &BProgram,
             draw_type: DrawType,
             vb: &BVertexBuffer,
-            uniforms: &Uniforms<u32>);
-    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32);
-    fn finish(self: Box<Self>);
+            uniforms: &Uniforms<u32>,
+            params: &DrawParameters)
+            -> Result<(), BackendError> {
+        self.draw_instanced(program, draw_type, vb, 1, uniforms, params)
+    }
+    /// Draws `vb` `instances` times in one call. Buffers with a divisor
+    /// advance once every `divisor` instances instead of per vertex.
+    fn draw_instanced(&mut self,
+                      program: &BProgram,
+                      draw_type: DrawType,
+                      vb: &BVertexBuffer,
+                      instances: usize,
+                      uniforms: &Uniforms<u32>,
+                      params: &DrawParameters)
+                      -> Result<(), BackendError> {
+        self.multi_draw_instanced(program,
+                                  draw_type,
+                                  vb,
+                                  &[0..vb.count()],
+                                  instances,
+                                  uniforms,
+                                  params)
+    }
+    /// Draws only the vertices, or indices if indexed, in `range`.
+    fn draw_range(&mut self,
+                  program: &BProgram,
+                  draw_type: DrawType,
+                  vb: &BVertexBuffer,
+                  range: Range<usize>,
+                  uniforms: &Uniforms<u32>,
+                  params: &DrawParameters)
+                  -> Result<(), BackendError> {
+        self.multi_draw_instanced(program, draw_type, vb, &[range], 1, uniforms, params)
+    }
+    /// Draws each of `ranges` as a separate batch of primitives in one call.
+    fn multi_draw(&mut self,
+                  program: &BProgram,
+                  draw_type: DrawType,
+                  vb: &BVertexBuffer,
+                  ranges: &[Range<usize>],
+                  uniforms: &Uniforms<u32>,
+                  params: &DrawParameters)
+                  -> Result<(), BackendError> {
+        self.multi_draw_instanced(program, draw_type, vb, ranges, 1, uniforms, params)
+    }
+    fn multi_draw_instanced(&mut self,
+                            program: &BProgram,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError>;
+    /// Clears the whole frame: color to RGBA, depth and stencil to the
+    /// given values. `None` leaves a buffer as it is.
+    fn clear(&mut self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>);
+    /// Size in pixels of what the frame draws into, which for the window
+    /// follows its resizes.
+    fn dimensions(&self) -> (u32, u32);
+    /// Width in pixels of the lines drawn afterwards. Core profile contexts
+    /// may only support 1.0.
+    fn line_width(&self, width: f32);
+    /// Size in pixels of the points drawn afterwards, unless the vertex
+    /// shader writes `gl_PointSize`. Software shaders cannot write it.
+    fn point_size(&self, size: f32);
+    /// Pixels drawn so far in this frame, top row first.
+    fn read_pixels(&self) -> RgbaImage;
+    fn finish(self: Box<Self>) -> Result<(), BackendError>;
 }
 
 pub trait Program {
-    fn draw(&self, draw_type: DrawType, vb: &BVertexBuffer, uniforms: &Uniforms<u32>);
+    fn draw(&self,
+            draw_type: DrawType,
+            vb: &BVertexBuffer,
+            uniforms: &Uniforms<u32>,
+            params: &DrawParameters)
+            -> Result<(), BackendError> {
+        self.draw_instanced(draw_type, vb, 1, uniforms, params)
+    }
+    fn draw_instanced(&self,
+                      draw_type: DrawType,
+                      vb: &BVertexBuffer,
+                      instances: usize,
+                      uniforms: &Uniforms<u32>,
+                      params: &DrawParameters)
+                      -> Result<(), BackendError> {
+        self.multi_draw_instanced(draw_type, vb, &[0..vb.count()], instances, uniforms, params)
+    }
+    /// Draws each of `ranges` of the vertices, or indices if indexed, as a
+    /// separate batch of primitives, `instances` times.
+    fn multi_draw_instanced(&self,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError>;
     fn get_bind(&self) -> u32;
+    /// Active vertex attributes of the linked program.
+    fn attributes(&self) -> &Vec<ProgramInput>;
+    /// Active uniforms of the linked program. Arrays are reported once, as
+    /// `name[0]` with their length in `size`.
+    fn uniforms(&self) -> &Vec<ProgramInput>;
+    /// Names of uniforms passed to draws that the program does not use, so
+    /// that callers can report them however they like.
+    fn unused_uniforms(&self) -> Vec<String>;
+    /// Makes the uniform block `name` read from `buffer` until it is bound
+    /// to another buffer. Any number of programs can share one buffer.
+    fn bind_uniform_block(&self, name: &str, buffer: &BUniformBuffer) -> Result<(), BackendError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramInput {
+    pub name: String,
+    pub ty: DataType,
+    /// Array length, 1 for non-arrays.
+    pub size: usize,
+    /// -1 for uniforms that live in a uniform block.
+    pub location: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    IVec2,
+    IVec3,
+    IVec4,
+    UInt,
+    UVec2,
+    UVec3,
+    UVec4,
+    Bool,
+    BVec2,
+    BVec3,
+    BVec4,
+    Mat2,
+    Mat3,
+    Mat4,
+    Mat2x3,
+    Mat2x4,
+    Mat3x2,
+    Mat3x4,
+    Mat4x2,
+    Mat4x3,
+    Sampler2D,
+    Other,
+}
+
+impl DataType {
+    pub fn from_glsl(name: &str) -> DataType {
+        use DataType::*;
+        match name {
+            "float" => Float,
+            "vec2" => Vec2,
+            "vec3" => Vec3,
+            "vec4" => Vec4,
+            "int" => Int,
+            "ivec2" => IVec2,
+            "ivec3" => IVec3,
+            "ivec4" => IVec4,
+            "uint" => UInt,
+            "uvec2" => UVec2,
+            "uvec3" => UVec3,
+            "uvec4" => UVec4,
+            "bool" => Bool,
+            "bvec2" => BVec2,
+            "bvec3" => BVec3,
+            "bvec4" => BVec4,
+            "mat2" | "mat2x2" => Mat2,
+            "mat3" | "mat3x3" => Mat3,
+            "mat4" | "mat4x4" => Mat4,
+            "mat2x3" => Mat2x3,
+            "mat2x4" => Mat2x4,
+            "mat3x2" => Mat3x2,
+            "mat3x4" => Mat3x4,
+            "mat4x2" => Mat4x2,
+            "mat4x3" => Mat4x3,
+            "sampler2D" => Sampler2D,
+            _ => Other,
+        }
+    }
+    pub fn is_integer(self) -> bool {
+        use DataType::*;
+        match self {
+            Int | IVec2 | IVec3 | IVec4 | UInt | UVec2 | UVec3 | UVec4 => true,
+            _ => false,
+        }
+    }
+    /// Number of components of a scalar or vector type.
+    pub fn components(self) -> Option<usize> {
+        use DataType::*;
+        match self {
+            Float | Int | UInt | Bool => Some(1),
+            Vec2 | IVec2 | UVec2 | BVec2 => Some(2),
+            Vec3 | IVec3 | UVec3 | BVec3 => Some(3),
+            Vec4 | IVec4 | UVec4 | BVec4 => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// Looks `name` up in reflected inputs, also matching the `name[0]` that
+/// arrays are reported as, any element `name[i]` of them, and members of
+/// structs that were reflected as a whole (`lights[2].color`).
+pub fn find_input<'a>(inputs: &'a [ProgramInput], name: &str) -> Option<&'a ProgramInput> {
+    let array = |base: &str| format!("{}[0]", base);
+    let found = inputs.iter().find(|i| i.name == name || i.name == array(name));
+    if found.is_some() {
+        return found;
+    }
+    if let Some((base, _)) = split_index(name) {
+        return inputs.iter().find(|i| i.name == array(base));
+    }
+    match name.find(|c| c == '[' || c == '.') {
+        Some(end) => {
+            let root = &name[..end];
+            inputs.iter()
+                .find(|i| i.ty == DataType::Other && (i.name == root || i.name == array(root)))
+        }
+        None => None,
+    }
+}
+
+/// Splits `name[i]` into `name` and `i`.
+pub fn split_index(name: &str) -> Option<(&str, usize)> {
+    if !name.ends_with(']') {
+        return None;
+    }
+    let open = match name.rfind('[') {
+        Some(i) => i,
+        None => return None,
+    };
+    name[open + 1..name.len() - 1].parse().ok().map(|i| (&name[..open], i))
+}
+
+/// Checks the vertex inputs of a vertex buffer against the active
+/// attributes of `program`. Every attribute the program reads must be
+/// provided; inputs it does not read are left out of the draw.
+pub fn check_inputs(program: &Program, buffers: &[BBuffer]) -> Result<(), BackendError> {
+    let mut provided = Vec::new();
+    for input in buffers.iter().flat_map(|b| b.get_layout().attributes.iter()) {
+        let attribute = match find_input(program.attributes(), &input.name) {
+            Some(a) => a,
+            None => continue,
+        };
+        provided.push(&attribute.name);
+        let is_vector = attribute.ty.components().is_some();
+        if is_vector && attribute.ty.is_integer() != input.ty.is_integer() {
+            return Err(BackendError::InvalidAttributeType {
+                name: input.name.clone(),
+                expected: attribute.ty,
+                actual: input.ty,
+            });
+        }
+        match attribute.ty.components() {
+            Some(n) if n != input.components => {
+                return Err(BackendError::InvalidAttributeSize {
+                    name: input.name.clone(),
+                    expected: n,
+                    actual: input.components,
+                })
+            }
+            _ => (),
+        }
+    }
+    // built-ins like gl_VertexID are active but not fed from buffers
+    let missing = program.attributes()
+        .iter()
+        .find(|a| !a.name.starts_with("gl_") && !provided.contains(&&a.name));
+    match missing {
+        Some(a) => Err(BackendError::InvalidAttribute { name: a.name.clone() }),
+        None => Ok(()),
+    }
+}
+
+/// Checks the layout of every buffer and that the indices, if any, are in
+/// range, as `VertexBufferBuilder::build` does, and returns the largest
+/// index for `VertexBuffer::max_index`.
+pub fn check_buffers(buffers: &[BBuffer],
+                     indices: Option<&IndexBuffer>,
+                     primitive_restart: bool)
+                     -> Result<Option<u32>, BackendError> {
+    for buffer in buffers.iter() {
+        try!(buffer.get_layout().check(buffer.get_data().len()));
+    }
+    match indices {
+        Some(indices) => {
+            try!(indices.check(vertex_count(buffers), primitive_restart));
+            Ok(indices.max(primitive_restart))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The names of uniforms a program was given but does not use, in the order
+/// they were first seen, each recorded once.
+pub struct UniformWarnings {
+    unused: RefCell<Vec<String>>,
+}
+
+impl UniformWarnings {
+    pub fn new() -> UniformWarnings {
+        UniformWarnings { unused: RefCell::new(Vec::new()) }
+    }
+    /// Whether `name` was recorded as unused.
+    pub fn contains(&self, name: &str) -> bool {
+        self.unused.borrow().iter().any(|n| n == name)
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.unused.borrow().clone()
+    }
+    fn warn(&self, name: &str) {
+        if !self.contains(name) {
+            self.unused.borrow_mut().push(String::from(name));
+        }
+    }
 }
 
+/// Checks `uniforms` against the active uniforms of `program`. Names the
+/// program does not use are not errors, since drivers drop uniforms that do
+/// not affect the output; they are recorded in `warnings` instead.
+pub fn check_uniforms<T>(program: &Program,
+                         uniforms: &Uniforms<T>,
+                         warnings: &UniformWarnings)
+                         -> Result<(), BackendError> {
+    for (name, uniform) in uniforms.names.iter().zip(uniforms.uniforms.iter()) {
+        match find_input(program.uniforms(), name) {
+            Some(u) if !uniform.matches(u.ty) => {
+                return Err(BackendError::InvalidUniformType {
+                    name: name.clone(),
+                    expected: u.ty,
+                })
+            }
+            Some(..) => (),
+            None => warnings.warn(name),
+        }
+    }
+    Ok(())
+}
+
+/// A vertex buffer holding the attributes of its layout, interleaved.
 pub trait Buffer {
-    fn get_buffer(&self) -> &InputBuffer;
+    /// The raw vertex data, `len() * stride` bytes.
+    fn get_data(&self) -> &[u8];
+    fn get_layout(&self) -> &VertexLayout;
     fn get_bind(&self) -> u32;
-    fn elem_len(&self) -> usize {
-        self.get_buffer().elem_len()
-    }
+    fn usage(&self) -> BufferUsage;
     fn len(&self) -> usize {
-        self.get_buffer().len()
+        self.get_data().len() / self.get_layout().stride
+    }
+    /// Overwrites the bytes from `offset` on with `data`, which must fit in
+    /// the buffer.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), BackendError>;
+    /// Replaces the whole contents, possibly changing the number of
+    /// vertices. The old storage is orphaned, so draws still reading it are
+    /// not waited for.
+    fn set_data(&mut self, data: &[u8]) -> Result<(), BackendError>;
+    /// Grows or shrinks the buffer to `vertices`, new vertices zeroed.
+    fn resize(&mut self, vertices: usize) -> Result<(), BackendError> {
+        let mut data = self.get_data().to_vec();
+        data.resize(vertices * self.get_layout().stride, 0);
+        self.set_data(&data)
     }
 }
 
+/// Checks that `len` bytes written at `offset` fit in `size` bytes.
+pub fn check_write(size: usize, offset: usize, len: usize) -> Result<(), BackendError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= size => Ok(()),
+        end => {
+            Err(BackendError::InvalidBufferSize {
+                expected: end.unwrap_or(usize::MAX),
+                actual: size,
+            })
+        }
+    }
+}
+
+/// How often the contents of a buffer are expected to change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Written once, drawn many times.
+    Static,
+    /// Rewritten now and then, drawn many times.
+    Dynamic,
+    /// Rewritten for about every draw, as for particles and UI.
+    Stream,
+}
+
 pub trait VertexBuffer {
     fn get_buffers(&self) -> &Vec<BBuffer>;
-    fn get_binds(&self) -> Vec<u32> {
-        self.get_buffers().iter().map(|b| b.get_bind()).collect()
+    /// The buffer `i`, for updating its contents in place. Draws fail once
+    /// it shrinks below the largest index.
+    fn buffer_mut(&mut self, i: usize) -> &mut Buffer;
+    /// Names of the attributes over all buffers.
+    fn get_names(&self) -> Vec<String> {
+        self.get_buffers()
+            .iter()
+            .flat_map(|b| b.get_layout().attributes.iter().map(|a| a.name.clone()))
+            .collect()
     }
-    fn get_names(&self) -> &Vec<String>;
-    fn get_bind(&self) -> u32;
+    /// The backend's id for drawing this buffer with `program`: on OpenGL
+    /// the vertex array object matching the program's attribute locations,
+    /// for the recorder the id its commands name the buffer by, and 0 for
+    /// the software renderer.
+    fn vertex_array(&self, program: &Program) -> Result<u32, BackendError>;
+    fn get_indices(&self) -> Option<&IndexBuffer>;
+    /// Whether the largest index of the index type restarts strips and fans.
+    fn primitive_restart(&self) -> bool;
+    /// The largest index, found when the buffer was built, `None` without
+    /// indices.
+    fn max_index(&self) -> Option<u32>;
+    /// The number of vertices.
     fn len(&self) -> usize {
-        self.get_buffers()[0].len()
+        vertex_count(self.get_buffers())
+    }
+    /// The number of instances the per-instance buffers hold, `None`
+    /// without any.
+    fn instances(&self) -> Option<usize> {
+        self.get_buffers()
+            .iter()
+            .filter(|b| b.get_layout().divisor > 0)
+            .map(|b| b.len() * b.get_layout().divisor as usize)
+            .min()
+    }
+    /// The number of vertices a draw submits, i.e. of indices if indexed.
+    fn count(&self) -> usize {
+        match self.get_indices() {
+            Some(indices) => indices.len(),
+            None => self.len(),
+        }
+    }
+}
+
+/// The number of vertices in the per-vertex `buffers`.
+pub fn vertex_count(buffers: &[BBuffer]) -> usize {
+    buffers.iter().filter(|b| b.get_layout().divisor == 0).map(|b| b.len()).min().unwrap_or(0)
+}
+
+/// Checks that every range lies within the vertices, or indices, of `vb`.
+pub fn check_ranges(vb: &VertexBuffer, ranges: &[Range<usize>]) -> Result<(), BackendError> {
+    let count = vb.count();
+    match ranges.iter().find(|r| r.start > r.end || r.end > count) {
+        Some(range) => {
+            Err(BackendError::InvalidRange {
+                range: range.clone(),
+                count: count,
+            })
+        }
+        None => Ok(()),
     }
 }
 
-pub trait VertexBufferBuilder {
-    fn add_input(mut self, name: &str, input: InputBuffer) -> Self;
-    fn build(self, program: &BProgram) -> BVertexBuffer;
+/// Checks that the largest index of `vb` still names one of its vertices,
+/// which resizing its buffers may have changed.
+pub fn check_indices(vb: &VertexBuffer) -> Result<(), BackendError> {
+    match vb.max_index() {
+        Some(index) if index as usize >= vb.len() => {
+            Err(BackendError::InvalidIndex {
+                index: index,
+                vertices: vb.len(),
+            })
+        }
+        _ => Ok(()),
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Checks that the per-instance buffers of `vb` hold `instances` instances.
+pub fn check_instances(vb: &VertexBuffer, instances: usize) -> Result<(), BackendError> {
+    match vb.instances() {
+        Some(available) if instances > available => {
+            Err(BackendError::InvalidInstanceCount {
+                instances: instances,
+                available: available,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub trait VertexBufferBuilder: Sized {
+    /// Adds one buffer holding the attributes of `layout`, which `build`
+    /// checks `data` against.
+    fn add_buffer<T: Copy>(self, layout: VertexLayout, data: &[T]) -> Self;
+    /// Sets the usage of the buffers added afterwards, `Static` by default.
+    fn usage(self, usage: BufferUsage) -> Self;
+    fn add_input(self, name: &str, input: InputBuffer) -> Self {
+        self.add_buffer(input.layout(name), input.as_bytes())
+    }
+    /// Adds one buffer holding every field of `V` interleaved.
+    fn add_vertices<V: ShaderInputs>(self, vertices: &[V]) -> Self {
+        self.add_buffer(V::layout(), vertices)
+    }
+    /// Adds an input advancing once every `divisor` instances.
+    fn add_instance_input(self, name: &str, input: InputBuffer, divisor: u32) -> Self {
+        self.add_buffer(input.layout(name).divisor(divisor), input.as_bytes())
+    }
+    /// Adds one buffer holding every field of `V`, one per instance.
+    fn add_instances<V: ShaderInputs>(self, instances: &[V]) -> Self {
+        self.add_buffer(V::layout().divisor(1), instances)
+    }
+    /// Draws the vertices in the order of `indices` rather than as stored.
+    fn indices(self, indices: IndexBuffer) -> Self;
+    /// Makes `IndexBuffer::restart_index` start a new strip or fan.
+    fn primitive_restart(self, enabled: bool) -> Self;
+    /// Builds a vertex buffer that any program reading a subset of its
+    /// inputs can draw.
+    fn build(self) -> Result<BVertexBuffer, BackendError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexBuffer {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl IndexBuffer {
+    pub fn len(&self) -> usize {
+        use IndexBuffer::*;
+        match self {
+            &U8(ref v) => v.len(),
+            &U16(ref v) => v.len(),
+            &U32(ref v) => v.len(),
+        }
+    }
+    pub fn get(&self, i: usize) -> u32 {
+        use IndexBuffer::*;
+        match self {
+            &U8(ref v) => v[i] as u32,
+            &U16(ref v) => v[i] as u32,
+            &U32(ref v) => v[i],
+        }
+    }
+    /// Bytes per index.
+    pub fn size(&self) -> usize {
+        use IndexBuffer::*;
+        match self {
+            &U8(..) => 1,
+            &U16(..) => 2,
+            &U32(..) => 4,
+        }
+    }
+    /// The largest value of the index type.
+    pub fn restart_index(&self) -> u32 {
+        use IndexBuffer::*;
+        match self {
+            &U8(..) => 0xff,
+            &U16(..) => 0xffff,
+            &U32(..) => 0xffffffff,
+        }
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        use IndexBuffer::*;
+        match self {
+            &U8(ref v) => v.as_slice(),
+            &U16(ref v) => slice_as_bytes(v),
+            &U32(ref v) => slice_as_bytes(v),
+        }
+    }
+    /// The largest index, leaving out the restart index if
+    /// `primitive_restart`.
+    pub fn max(&self, primitive_restart: bool) -> Option<u32> {
+        (0..self.len())
+            .map(|i| self.get(i))
+            .filter(|&index| !(primitive_restart && index == self.restart_index()))
+            .max()
+    }
+    /// Checks that every index but the restart one names one of `vertices`.
+    pub fn check(&self, vertices: usize, primitive_restart: bool) -> Result<(), BackendError> {
+        for i in 0..self.len() {
+            let index = self.get(i);
+            if index as usize >= vertices && !(primitive_restart && index == self.restart_index()) {
+                return Err(BackendError::InvalidIndex {
+                    index: index,
+                    vertices: vertices,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    F32,
+    /// IEEE 754 half float.
+    F16,
+    /// Read by the shader as integers, without conversion to float.
+    I32,
+    /// Read by the shader as integers, without conversion to float.
+    U32,
+    /// Unsigned bytes mapped to 0.0..1.0.
+    U8Norm,
+    /// Signed shorts mapped to -1.0..1.0.
+    I16Norm,
+}
+
+impl AttributeType {
+    pub fn size(self) -> usize {
+        use AttributeType::*;
+        match self {
+            F32 | I32 | U32 => 4,
+            F16 | I16Norm => 2,
+            U8Norm => 1,
+        }
+    }
+    /// Whether the shader input is an `int`/`uint` type rather than a float.
+    pub fn is_integer(self) -> bool {
+        self == AttributeType::I32 || self == AttributeType::U32
+    }
+}
+
+/// An attribute inside the vertices of a buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub ty: AttributeType,
+    pub components: usize,
+    /// Byte offset from the start of a vertex.
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    /// Bytes from one vertex to the next.
+    pub stride: usize,
+    pub attributes: Vec<VertexAttribute>,
+    /// Instances drawn per element, 0 to advance per vertex instead.
+    pub divisor: u32,
+}
+
+impl VertexLayout {
+    pub fn new(stride: usize) -> VertexLayout {
+        VertexLayout {
+            stride: stride,
+            attributes: Vec::new(),
+            divisor: 0,
+        }
+    }
+    pub fn divisor(mut self, divisor: u32) -> VertexLayout {
+        self.divisor = divisor;
+        self
+    }
+    pub fn attribute(mut self,
+                     name: &str,
+                     ty: AttributeType,
+                     components: usize,
+                     offset: usize)
+                     -> VertexLayout {
+        self.attributes.push(VertexAttribute {
+            name: String::from(name),
+            ty: ty,
+            components: components,
+            offset: offset,
+        });
+        self
+    }
+    /// Checks that every attribute fits in a vertex and that `data_len`
+    /// bytes hold whole vertices.
+    pub fn check(&self, data_len: usize) -> Result<(), BackendError> {
+        let invalid = |reason: String| Err(BackendError::InvalidLayout(reason));
+        // before the stride, which a single attribute of 0 components makes 0
+        for a in self.attributes.iter() {
+            if a.components < 1 || a.components > 4 {
+                return invalid(format!("`{}` has {} components", a.name, a.components));
+            }
+        }
+        if self.stride == 0 {
+            return invalid(String::from("stride is 0"));
+        }
+        if data_len % self.stride != 0 {
+            return invalid(format!("{} bytes is not a multiple of the stride {}",
+                                   data_len,
+                                   self.stride));
+        }
+        for a in self.attributes.iter() {
+            if a.offset + a.components * a.ty.size() > self.stride {
+                return invalid(format!("`{}` ends past the stride {}", a.name, self.stride));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A Rust type usable as a field of a `ShaderInputs` vertex.
+pub trait Attribute: Copy {
+    const TYPE: AttributeType;
+    const COMPONENTS: usize;
+}
+
+macro_rules! impl_attribute {
+    ($($ty:ty => ($attr:ident, $n:expr)),*) => (
+        $(impl Attribute for $ty {
+            const TYPE: AttributeType = AttributeType::$attr;
+            const COMPONENTS: usize = $n;
+        })*
+    );
+}
+
+impl_attribute!(f32 => (F32, 1), [f32; 2] => (F32, 2), [f32; 3] => (F32, 3), [f32; 4] => (F32, 4),
+                i32 => (I32, 1), [i32; 2] => (I32, 2), [i32; 3] => (I32, 3), [i32; 4] => (I32, 4),
+                u32 => (U32, 1), [u32; 2] => (U32, 2), [u32; 3] => (U32, 3), [u32; 4] => (U32, 4),
+                [u8; 4] => (U8Norm, 4),
+                [i16; 2] => (I16Norm, 2), [i16; 3] => (I16Norm, 3), [i16; 4] => (I16Norm, 4));
+
+/// A `#[repr(C)]` vertex struct whose fields are attributes of the same
+/// name. Implemented with `impl_shader_inputs!`.
+pub trait ShaderInputs: Copy {
+    fn layout() -> VertexLayout;
+}
+
+#[doc(hidden)]
+pub fn attribute_of<T: Attribute>(name: &str, offset: usize, _: *const T) -> VertexAttribute {
+    VertexAttribute {
+        name: String::from(name),
+        ty: T::TYPE,
+        components: T::COMPONENTS,
+        offset: offset,
+    }
+}
+
+/// Implements `ShaderInputs` for a `#[repr(C)]` struct from the fields
+/// that are passed to the shader:
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// struct Vertex {
+///     position: [f32; 3],
+///     coord: [f32; 2],
+/// }
+///
+/// impl_shader_inputs!(Vertex, position, coord);
+/// ```
+#[macro_export]
+macro_rules! impl_shader_inputs {
+    ($name:ty, $($field:ident),+) => (
+        impl $crate::ShaderInputs for $name {
+            fn layout() -> $crate::VertexLayout {
+                let vertex = ::std::mem::MaybeUninit::<$name>::uninit();
+                let base = vertex.as_ptr();
+                $crate::VertexLayout {
+                    stride: ::std::mem::size_of::<$name>(),
+                    attributes: vec![$(
+                        $crate::attribute_of(stringify!($field),
+                                             ::std::mem::offset_of!($name, $field),
+                                             unsafe { ::std::ptr::addr_of!((*base).$field) })
+                    ),+],
+                    divisor: 0,
+                }
+            }
+        }
+    );
+}
+
+/// Views a slice of plain data as its bytes.
+pub fn slice_as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>()) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DrawType {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
     Triangles,
     TriangleStrip,
+    TriangleFan,
+    /// Adjacency primitives, for a geometry shader to read the neighbours of
+    /// each line or triangle. Drawn as their plain primitive without one.
+    LinesAdjacency,
+    LineStripAdjacency,
+    TrianglesAdjacency,
+    TriangleStripAdjacency,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum InputBuffer {
+    Float(Vec<f32>),
     Vec2(Vec<f32>),
     Vec3(Vec<f32>),
+    Vec4(Vec<f32>),
+    /// `int`/`ivec` components, as many per vertex as the first field says.
+    Int(usize, Vec<i32>),
+    /// `uint`/`uvec` components, as many per vertex as the first field says.
+    UInt(usize, Vec<u32>),
+    /// Packed colors, read by the shader as a `vec4` in 0.0..1.0.
+    U8x4Norm(Vec<[u8; 4]>),
+    /// Components read by the shader as floats in -1.0..1.0.
+    I16Norm(usize, Vec<i16>),
+    /// Half floats given as their IEEE 754 binary16 bits.
+    Half(usize, Vec<u16>),
 }
 
 impl InputBuffer {
+    /// The number of elements, 0 for a component count of 0, which building
+    /// the vertex buffer rejects.
     pub fn len(&self) -> usize {
-        use InputBuffer::*;
-        match self {
-            &Vec2(ref v) => v.len() / 2,
-            &Vec3(ref v) => v.len() / 3,
-        }
+        self.as_bytes()
+            .len()
+            .checked_div(self.elem_len() * self.attribute_type().size())
+            .unwrap_or(0)
     }
     pub fn elem_len(&self) -> usize {
         use InputBuffer::*;
         match self {
+            &Float(..) => 1,
             &Vec2(..) => 2,
             &Vec3(..) => 3,
+            &Vec4(..) | &U8x4Norm(..) => 4,
+            &Int(n, _) | &UInt(n, _) | &I16Norm(n, _) | &Half(n, _) => n,
         }
     }
-    pub fn buffer_size(&self) -> usize {
+    pub fn attribute_type(&self) -> AttributeType {
         use InputBuffer::*;
         match self {
-            &Vec2(ref v) => v.len() * mem::size_of::<f32>(),
-            &Vec3(ref v) => v.len() * mem::size_of::<f32>(),
+            &Float(..) | &Vec2(..) | &Vec3(..) | &Vec4(..) => AttributeType::F32,
+            &Int(..) => AttributeType::I32,
+            &UInt(..) => AttributeType::U32,
+            &U8x4Norm(..) => AttributeType::U8Norm,
+            &I16Norm(..) => AttributeType::I16Norm,
+            &Half(..) => AttributeType::F16,
         }
     }
-    pub fn as_slice(&self) -> &[f32] {
+    pub fn buffer_size(&self) -> usize {
+        self.as_bytes().len()
+    }
+    pub fn as_bytes(&self) -> &[u8] {
         use InputBuffer::*;
         match self {
-            &Vec2(ref raw) => raw.as_slice(),
-            &Vec3(ref raw) => raw.as_slice(),
+            &Float(ref v) | &Vec2(ref v) | &Vec3(ref v) | &Vec4(ref v) => slice_as_bytes(v),
+            &Int(_, ref v) => slice_as_bytes(v),
+            &UInt(_, ref v) => slice_as_bytes(v),
+            &U8x4Norm(ref v) => slice_as_bytes(v),
+            &I16Norm(_, ref v) => slice_as_bytes(v),
+            &Half(_, ref v) => slice_as_bytes(v),
         }
     }
+    /// The layout of the data as a buffer holding only the attribute `name`.
+    pub fn layout(&self, name: &str) -> VertexLayout {
+        let ty = self.attribute_type();
+        VertexLayout::new(self.elem_len() * ty.size()).attribute(name, ty, self.elem_len(), 0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Geometry,
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ShaderStage::*;
+        f.write_str(match *self {
+            Vertex => "vertex",
+            Fragment => "fragment",
+            Geometry => "geometry",
+        })
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColorFormat {
     RGB,
     RGBA,
@@ -159,16 +1007,92 @@ impl ColorFormat {
             RGBA => 4,
         }
     }
+    pub fn check_data(self, width: u32, height: u32, data: &[u8]) -> Result<(), BackendError> {
+        let expected = self.size() * width as usize * height as usize;
+        if data.len() != expected {
+            return Err(BackendError::InvalidTextureData {
+                expected: expected,
+                actual: data.len(),
+            });
+        }
+        Ok(())
+    }
 }
 
-#[derive(Clone, Debug)]
+/// A uniform value. Matrices are given row by row; `MatCxR` has C columns
+/// and R rows as in GLSL, and `Matrix` is a `mat4`.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Uniform<T> {
+    Float(f32),
+    Int(i32),
+    UInt(u32),
+    Bool(bool),
     Vec2(f32, f32),
     Vec3(f32, f32, f32),
+    Vec4(f32, f32, f32, f32),
+    IVec2(i32, i32),
+    IVec3(i32, i32, i32),
+    IVec4(i32, i32, i32, i32),
+    UVec2(u32, u32),
+    UVec3(u32, u32, u32),
+    UVec4(u32, u32, u32, u32),
+    BVec2(bool, bool),
+    BVec3(bool, bool, bool),
+    BVec4(bool, bool, bool, bool),
+    Mat2([[f32; 2]; 2]),
+    Mat3([[f32; 3]; 3]),
     Matrix([[f32; 4]; 4]),
+    Mat2x3([[f32; 2]; 3]),
+    Mat2x4([[f32; 2]; 4]),
+    Mat3x2([[f32; 3]; 2]),
+    Mat3x4([[f32; 3]; 4]),
+    Mat4x2([[f32; 4]; 2]),
+    Mat4x3([[f32; 4]; 3]),
     Texture2D(T),
+    /// The elements of an array uniform, starting at index 0.
+    Array(Vec<Uniform<T>>),
 }
 
+impl<T> Uniform<T> {
+    /// Whether the value can be assigned to a uniform of type `ty`. Arrays
+    /// match the type of their elements.
+    pub fn matches(&self, ty: DataType) -> bool {
+        use Uniform::*;
+        match (self, ty) {
+            (_, DataType::Other) => true,
+            (&Array(ref elems), ty) => elems.iter().all(|e| e.matches(ty)),
+            (&Float(..), DataType::Float) => true,
+            (&Int(..), DataType::Int) => true,
+            (&UInt(..), DataType::UInt) => true,
+            (&Bool(..), DataType::Bool) => true,
+            (&Vec2(..), DataType::Vec2) => true,
+            (&Vec3(..), DataType::Vec3) => true,
+            (&Vec4(..), DataType::Vec4) => true,
+            (&IVec2(..), DataType::IVec2) => true,
+            (&IVec3(..), DataType::IVec3) => true,
+            (&IVec4(..), DataType::IVec4) => true,
+            (&UVec2(..), DataType::UVec2) => true,
+            (&UVec3(..), DataType::UVec3) => true,
+            (&UVec4(..), DataType::UVec4) => true,
+            (&BVec2(..), DataType::BVec2) => true,
+            (&BVec3(..), DataType::BVec3) => true,
+            (&BVec4(..), DataType::BVec4) => true,
+            (&Mat2(..), DataType::Mat2) => true,
+            (&Mat3(..), DataType::Mat3) => true,
+            (&Matrix(..), DataType::Mat4) => true,
+            (&Mat2x3(..), DataType::Mat2x3) => true,
+            (&Mat2x4(..), DataType::Mat2x4) => true,
+            (&Mat3x2(..), DataType::Mat3x2) => true,
+            (&Mat3x4(..), DataType::Mat3x4) => true,
+            (&Mat4x2(..), DataType::Mat4x2) => true,
+            (&Mat4x3(..), DataType::Mat4x3) => true,
+            (&Texture2D(..), DataType::Sampler2D) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Uniforms<T> {
     pub names: Vec<String>,
     pub uniforms: Vec<Uniform<T>>,
@@ -185,85 +1109,100 @@ impl<T> Uniforms<T> {
         self.names.push(String::from(name));
         self.uniforms.push(uniform);
     }
+    /// Looks a uniform up by name. `name[i]` also finds element `i` of an
+    /// `Array` added as `name`.
+    pub fn get(&self, name: &str) -> Option<&Uniform<T>> {
+        if let Some(i) = self.names.iter().position(|n| n == name) {
+            return Some(&self.uniforms[i]);
+        }
+        let (base, index) = match split_index(name) {
+            Some(split) => split,
+            None => return None,
+        };
+        match self.get(base) {
+            Some(&Uniform::Array(ref elems)) => elems.get(index),
+            _ => None,
+        }
+    }
 }
 
 pub trait Texture2D {
     fn get_bind(&self) -> u32;
+    fn dimensions(&self) -> (u32, u32);
     fn as_uniform(&self) -> Uniform<u32> {
         Uniform::Texture2D(self.get_bind())
     }
 }
 
+pub trait UniformBuffer {
+    fn get_bind(&self) -> u32;
+    fn size(&self) -> usize;
+    /// Replaces the contents; `data` must be exactly `size()` bytes long.
+    fn write(&self, data: &[u8]) -> Result<(), BackendError>;
+}
+
+impl UniformBuffer {
+    /// Replaces the contents with `data`, which must be the type the buffer
+    /// was created from.
+    pub fn update<T: Std140>(&self, data: &T) -> Result<(), BackendError> {
+        self.write(&std140::to_bytes(data))
+    }
+}
+
+/// Depth and stencil storage of a `Framebuffer`, which only the
+/// framebuffer itself can read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthStencil {
+    None,
+    /// A 24 bit depth buffer.
+    Depth,
+    /// A 24 bit depth buffer with an 8 bit stencil buffer.
+    DepthStencil,
+}
+
+/// Color textures with optional depth and stencil buffers to draw into
+/// instead of the window. The textures can be sampled as uniforms by later
+/// passes once the frames drawing into them are finished.
+pub trait Framebuffer {
+    fn get_bind(&self) -> u32;
+    fn dimensions(&self) -> (u32, u32);
+    /// A frame drawing into the framebuffer. Finishing it presents nothing.
+    fn frame(&self) -> BFrame;
+}
+
+/// Checks that `colors` is not empty and holds textures of one size,
+/// returning that size.
+pub fn check_attachments(colors: &[&BTexture2D]) -> Result<(u32, u32), BackendError> {
+    let size = match colors.first() {
+        Some(texture) => texture.dimensions(),
+        None => return Err(BackendError::InvalidFramebuffer(String::from("no color attachment"))),
+    };
+    match colors.iter().find(|t| t.dimensions() != size) {
+        Some(texture) => {
+            Err(BackendError::InvalidFramebuffer(format!("attachments of {:?} and {:?} pixels",
+                                                         size,
+                                                         texture.dimensions())))
+        }
+        None => Ok(size),
+    }
+}
+
+/// Builds `Uniforms` from `name: value` pairs. Names that are not plain
+/// identifiers are given as string literals, e.g. `"lights[2].color"`.
 #[macro_export]
 macro_rules! uniforms {
-    ($($name:ident : $val:expr),*) => (
+    ($($name:tt : $val:expr),* $(,)*) => (
         {
             let mut uniforms = Uniforms::new();
-            $(uniforms.add_uniform(stringify!($name), $val);),*
+            $(uniforms.add_uniform(uniform_name!($name), $val);)*
             uniforms
         }
     );
 }
 
-// pub trait ShaderInputs {
-//     fn get_names<'a>() -> Vec<&'a str>;
-//     fn get_inputs(&self) -> Vec<ShaderInput>;
-// }
-//
-// #[derive(Clone, Debug)]
-// pub enum Uniform {
-//     Vec2(f32, f32),
-//     Vec3(f32, f32, f32),
-//     Matrix([[f32; 4]; 4]),
-//     Texture2D(usize, usize, ColorFormat /* &[u8] */),
-// }
-//
-// pub trait Uniforms {
-//     fn get_names<'a>() -> Vec<&'a str>;
-//     fn get_params(&self) -> Vec<Uniform>;
-// }
-//
-
-// macro_rules! impl_shader_param {
-//     ($from:ty, $to:ident, $($field:ident),+) => (
-//         impl $from {
-//             pub fn into_param(&self) -> ShaderParam {
-//                 ShaderParam::$to($(self.$field),+)
-//             }
-//         }
-//     );
-// }
-//
-// macro_rules! impl_vertex_params {
-//     ($name:ty, $($field:ident),+) => (
-//         impl ShaderInput for $name {
-//             fn get_binds(&self) -> Vec<ShaderParam> {
-//                 vec![$(self.$field.into_param()),+]
-//             }
-//             fn get_names<'a>() -> Vec<&'a str> {
-//                 vec![$(stringify!($field)),+]
-//             }
-//         }
-//     );
-// }
-
-// pub struct Vertex {
-//     pos: Vector3f,
-//     color: Vector2f,
-// }
-//
-// impl_shader_param!(Vector3f, Vec3, x, y, z);
-// =>
-// impl Vector3f {
-//     pub fn into_param(&self) -> ShaderParam {
-//         ShaderParam::Vec3(x, y, z)
-//     }
-// }
-
-// impl_vertex_params!(Vertex, pos, color);
-// // =>
-// // impl VertexParams for Vertex {
-// //     fn get_params(&self) -> Vec<ShaderParam> {
-// //         vec![pos.into_param(), color.into_param()]
-// //     }
-// // }
+#[doc(hidden)]
+#[macro_export]
+macro_rules! uniform_name {
+    ($name:ident) => (stringify!($name));
+    ($name:expr) => ($name);
+}
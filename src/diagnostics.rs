@@ -0,0 +1,179 @@
+use std::fmt::Write;
+
+use super::backend::ShaderStage;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e {
+        Some(v) => v,
+        None => return None,
+    })
+}
+
+/// Lines of source shown before and after the offending one.
+pub const CONTEXT_LINES: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One message of a shader info log, mapped back to the source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// 1-based, as reported by the driver.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+    pub source_line: Option<String>,
+    /// The offending line and its neighbours as (line number, text).
+    pub context: Vec<(usize, String)>,
+}
+
+/// Parses the info log formats of Mesa (`0:12(5): error: msg`), NVIDIA
+/// (`0(12) : error C0000: msg`) and AMD (`ERROR: 0:12: msg`). Lines that
+/// match none of them are kept as diagnostics without a location.
+pub fn parse_log(log: &str, source: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = source.lines().collect();
+    log.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let (severity, line, column, message) = parse_mesa(l)
+                .or_else(|| parse_nvidia(l))
+                .or_else(|| parse_amd(l))
+                .unwrap_or_else(|| (guess_severity(l), None, None, String::from(l)));
+            let context = match line {
+                Some(n) if n >= 1 && n <= lines.len() => {
+                    let first = if n > CONTEXT_LINES { n - CONTEXT_LINES } else { 1 };
+                    let last = (n + CONTEXT_LINES).min(lines.len());
+                    (first..last + 1).map(|i| (i, String::from(lines[i - 1]))).collect()
+                }
+                _ => Vec::new(),
+            };
+            Diagnostic {
+                severity: severity,
+                line: line,
+                column: column,
+                message: message,
+                source_line: line.and_then(|n| lines.get(n.wrapping_sub(1)))
+                    .map(|s| String::from(*s)),
+                context: context,
+            }
+        })
+        .collect()
+}
+
+type Parsed = (Severity, Option<usize>, Option<usize>, String);
+
+fn parse_severity(word: &str) -> Option<Severity> {
+    match word.trim().to_lowercase().as_str() {
+        "error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        _ => None,
+    }
+}
+
+fn guess_severity(line: &str) -> Severity {
+    if line.to_lowercase().starts_with("warning") {
+        Severity::Warning
+    } else {
+        Severity::Error
+    }
+}
+
+fn parse_mesa(l: &str) -> Option<Parsed> {
+    // 0:12(5): error: msg
+    let colon = try_opt!(l.find(':'));
+    let open = try_opt!(l.find('('));
+    let close = try_opt!(l.find("):"));
+    if !(colon < open && open < close) {
+        return None;
+    }
+    try_opt!(l[..colon].parse::<usize>().ok());
+    let line = try_opt!(l[colon + 1..open].parse::<usize>().ok());
+    let column = try_opt!(l[open + 1..close].parse::<usize>().ok());
+    let rest = &l[close + 2..];
+    let sep = try_opt!(rest.find(':'));
+    let severity = try_opt!(parse_severity(&rest[..sep]));
+    Some((severity, Some(line), Some(column), String::from(rest[sep + 1..].trim())))
+}
+
+fn parse_nvidia(l: &str) -> Option<Parsed> {
+    // 0(12) : error C0000: msg
+    let open = try_opt!(l.find('('));
+    let close = try_opt!(l.find(')'));
+    if open > close {
+        return None;
+    }
+    try_opt!(l[..open].parse::<usize>().ok());
+    let line = try_opt!(l[open + 1..close].parse::<usize>().ok());
+    let rest = l[close + 1..].trim_start();
+    if !rest.starts_with(':') {
+        return None;
+    }
+    let rest = rest[1..].trim_start();
+    let sep = try_opt!(rest.find(':'));
+    let severity = try_opt!(rest[..sep].split_whitespace().next().and_then(parse_severity));
+    Some((severity, Some(line), None, String::from(rest[sep + 1..].trim())))
+}
+
+fn parse_amd(l: &str) -> Option<Parsed> {
+    // ERROR: 0:12: msg
+    let mut parts = l.splitn(4, ':');
+    let severity = try_opt!(parts.next().and_then(parse_severity));
+    try_opt!(parts.next().and_then(|s| s.trim().parse::<usize>().ok()));
+    let line = try_opt!(parts.next().and_then(|s| s.trim().parse::<usize>().ok()));
+    let message = try_opt!(parts.next());
+    Some((severity, Some(line), None, String::from(message.trim())))
+}
+
+/// Formats `diagnostics` the way rustc reports errors:
+///
+/// ```text
+/// error: syntax error, unexpected IDENTIFIER
+///   --> vertex shader:4:5
+///    |
+///  3 |     in vec3 position
+///  4 |     out vec2 coord0;
+///    |     ^
+/// ```
+pub fn pretty_print(stage: ShaderStage, diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for d in diagnostics {
+        let severity = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(out, "{}: {}", severity, d.message).unwrap();
+        let line = match d.line {
+            Some(line) => line,
+            None => {
+                writeln!(out, "  --> {} shader", stage).unwrap();
+                continue;
+            }
+        };
+        match d.column {
+            Some(column) => writeln!(out, "  --> {} shader:{}:{}", stage, line, column).unwrap(),
+            None => writeln!(out, "  --> {} shader:{}", stage, line).unwrap(),
+        }
+        let width = d.context.iter().map(|&(n, _)| n.to_string().len()).max().unwrap_or(1);
+        writeln!(out, "{:w$} |", "", w = width).unwrap();
+        for &(n, ref text) in d.context.iter() {
+            writeln!(out, "{:>w$} | {}", n, text, w = width).unwrap();
+            if n == line {
+                let marker = match d.column {
+                    Some(column) if column > 0 => format!("{:>c$}", "^", c = column),
+                    _ => {
+                        let indent = text.len() - text.trim_start().len();
+                        format!("{:i$}{}", "", "^".repeat(text.trim().len().max(1)), i = indent)
+                    }
+                };
+                writeln!(out, "{:w$} | {}", "", marker, w = width).unwrap();
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,163 @@
+//! Headless contexts on EGL's surfaceless platform, for Linux machines with
+//! neither OSMesa nor a display server (e.g. Mesa llvmpipe on CI). libEGL is
+//! loaded at run time so that it is not needed to build or link.
+
+use shared_library::dynamic_library::DynamicLibrary;
+
+use glutin::GlProfile;
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::ptr;
+
+type EGLBoolean = u32;
+type EGLDisplay = *mut c_void;
+type EGLContext = *mut c_void;
+type EGLConfig = *mut c_void;
+type EGLSurface = *mut c_void;
+type EGLenum = u32;
+type EGLint = i32;
+
+const NONE: EGLint = 0x3038;
+const EXTENSIONS: EGLint = 0x3055;
+const OPENGL_API: EGLenum = 0x30A2;
+const PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+const CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+const CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+const CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+const CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x1;
+const CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: EGLint = 0x2;
+
+struct Egl {
+    // kept loaded for as long as the function pointers are used
+    _library: DynamicLibrary,
+    get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+    query_string: unsafe extern "C" fn(EGLDisplay, EGLint) -> *const c_char,
+    get_error: unsafe extern "C" fn() -> EGLint,
+    initialize: unsafe extern "C" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean,
+    bind_api: unsafe extern "C" fn(EGLenum) -> EGLBoolean,
+    create_context: unsafe extern "C" fn(EGLDisplay, EGLConfig, EGLContext, *const EGLint)
+                                         -> EGLContext,
+    destroy_context: unsafe extern "C" fn(EGLDisplay, EGLContext) -> EGLBoolean,
+    make_current: unsafe extern "C" fn(EGLDisplay, EGLSurface, EGLSurface, EGLContext)
+                                       -> EGLBoolean,
+}
+
+impl Egl {
+    fn load() -> Result<Egl, String> {
+        let library = try!(DynamicLibrary::open(Some(Path::new("libEGL.so.1"))));
+        let symbol = |name: &str| unsafe { library.symbol::<c_void>(name) };
+        unsafe {
+            Ok(Egl {
+                get_proc_address: mem::transmute(try!(symbol("eglGetProcAddress"))),
+                query_string: mem::transmute(try!(symbol("eglQueryString"))),
+                get_error: mem::transmute(try!(symbol("eglGetError"))),
+                initialize: mem::transmute(try!(symbol("eglInitialize"))),
+                bind_api: mem::transmute(try!(symbol("eglBindAPI"))),
+                create_context: mem::transmute(try!(symbol("eglCreateContext"))),
+                destroy_context: mem::transmute(try!(symbol("eglDestroyContext"))),
+                make_current: mem::transmute(try!(symbol("eglMakeCurrent"))),
+                _library: library,
+            })
+        }
+    }
+
+    fn error(&self, call: &str) -> String {
+        format!("{} failed (EGL error {:#x})", call, unsafe { (self.get_error)() })
+    }
+}
+
+pub struct EglContext {
+    egl: Egl,
+    display: EGLDisplay,
+    context: EGLContext,
+}
+
+impl EglContext {
+    /// Creates a context without a config or surface; it draws only into
+    /// framebuffer objects.
+    pub fn new(major: u8, minor: u8, profile: GlProfile) -> Result<EglContext, String> {
+        let egl = try!(Egl::load());
+        unsafe {
+            let extensions = (egl.query_string)(ptr::null_mut(), EXTENSIONS);
+            if extensions.is_null() ||
+               !CStr::from_ptr(extensions)
+                .to_string_lossy()
+                .split(' ')
+                .any(|e| e == "EGL_MESA_platform_surfaceless") {
+                return Err(String::from("EGL_MESA_platform_surfaceless is not supported"));
+            }
+            let name = CString::new("eglGetPlatformDisplayEXT").unwrap();
+            let get_platform_display = (egl.get_proc_address)(name.as_ptr());
+            if get_platform_display.is_null() {
+                return Err(String::from("eglGetPlatformDisplayEXT is missing"));
+            }
+            let get_platform_display: unsafe extern "C" fn(EGLenum, *mut c_void, *const EGLint)
+                                                           -> EGLDisplay =
+                mem::transmute(get_platform_display);
+            let display = get_platform_display(PLATFORM_SURFACELESS_MESA,
+                                               ptr::null_mut(),
+                                               ptr::null());
+            if display.is_null() {
+                return Err(egl.error("eglGetPlatformDisplayEXT"));
+            }
+            // the display is shared by every context of the process, so it is
+            // never terminated
+            let (mut major_egl, mut minor_egl) = (0, 0);
+            if (egl.initialize)(display, &mut major_egl, &mut minor_egl) == 0 {
+                return Err(egl.error("eglInitialize"));
+            }
+            if (egl.bind_api)(OPENGL_API) == 0 {
+                return Err(egl.error("eglBindAPI"));
+            }
+            let profile = match profile {
+                GlProfile::Core => CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                GlProfile::Compatibility => CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+            };
+            let attributes = [CONTEXT_MAJOR_VERSION,
+                              major as EGLint,
+                              CONTEXT_MINOR_VERSION,
+                              minor as EGLint,
+                              CONTEXT_OPENGL_PROFILE_MASK,
+                              profile,
+                              NONE];
+            let context = (egl.create_context)(display,
+                                               ptr::null_mut(),
+                                               ptr::null_mut(),
+                                               attributes.as_ptr());
+            if context.is_null() {
+                return Err(egl.error("eglCreateContext"));
+            }
+            Ok(EglContext {
+                egl: egl,
+                display: display,
+                context: context,
+            })
+        }
+    }
+
+    pub unsafe fn make_current(&self) -> Result<(), String> {
+        if (self.egl.make_current)(self.display, ptr::null_mut(), ptr::null_mut(), self.context) ==
+           0 {
+            return Err(self.egl.error("eglMakeCurrent"));
+        }
+        Ok(())
+    }
+
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        let name = CString::new(name).unwrap();
+        unsafe { (self.egl.get_proc_address)(name.as_ptr()) }
+    }
+}
+
+impl Drop for EglContext {
+    fn drop(&mut self) {
+        unsafe {
+            let none = ptr::null_mut();
+            (self.egl.make_current)(self.display, none, none, none);
+            (self.egl.destroy_context)(self.display, self.context);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use super::backend::{AttributeType, DataType, ShaderStage};
+use super::diagnostics::{self, Diagnostic};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendError {
+    ContextCreation(String),
+    /// The finished frame could not be presented, e.g. the context was lost.
+    SwapBuffers(String),
+    ShaderCompile {
+        stage: ShaderStage,
+        log: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    Link {
+        log: String,
+    },
+    /// An attribute the program reads that the vertex buffer does not provide.
+    InvalidAttribute {
+        name: String,
+    },
+    /// An `InputBuffer` whose element size differs from the attribute's.
+    InvalidAttributeSize {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    /// Integer data for a float attribute or the other way around.
+    InvalidAttributeType {
+        name: String,
+        expected: DataType,
+        actual: AttributeType,
+    },
+    /// A `Uniform` whose variant cannot be assigned to the uniform's type.
+    InvalidUniformType {
+        name: String,
+        expected: DataType,
+    },
+    /// An index past the end of the vertex buffer.
+    InvalidIndex {
+        index: u32,
+        vertices: usize,
+    },
+    /// A draw range past the end of the vertices, or indices if indexed.
+    InvalidRange {
+        range: Range<usize>,
+        count: usize,
+    },
+    /// More instances drawn than the per-instance buffers hold.
+    InvalidInstanceCount {
+        instances: usize,
+        available: usize,
+    },
+    /// A `VertexLayout` that does not fit its data.
+    InvalidLayout(String),
+    InvalidTextureData {
+        expected: usize,
+        actual: usize,
+    },
+    /// The program has no active uniform block called `name`.
+    InvalidUniformBlock {
+        name: String,
+    },
+    /// Framebuffer attachments that cannot be drawn into together.
+    InvalidFramebuffer(String),
+    InvalidBufferSize {
+        expected: usize,
+        actual: usize,
+    },
+    /// A name handed to the driver contains a NUL byte.
+    InvalidName(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::BackendError::*;
+        match self {
+            &ContextCreation(ref reason) => write!(f, "failed to create a context: {}", reason),
+            &SwapBuffers(ref reason) => write!(f, "failed to swap buffers: {}", reason),
+            &ShaderCompile { stage, ref log, ref diagnostics } => {
+                if diagnostics.is_empty() {
+                    write!(f, "failed to compile the {} shader:\n{}", stage, log)
+                } else {
+                    write!(f,
+                           "failed to compile the {} shader:\n{}",
+                           stage,
+                           diagnostics::pretty_print(stage, diagnostics))
+                }
+            }
+            &Link { ref log } => write!(f, "failed to link the program:\n{}", log),
+            &InvalidAttribute { ref name } => {
+                write!(f, "no vertex input provides the attribute `{}`", name)
+            }
+            &InvalidAttributeSize { ref name, expected, actual } => {
+                write!(f,
+                       "attribute `{}` has {} components but was given {}",
+                       name,
+                       expected,
+                       actual)
+            }
+            &InvalidAttributeType { ref name, expected, actual } => {
+                write!(f,
+                       "attribute `{}` is of type {:?} but was given {:?} data",
+                       name,
+                       expected,
+                       actual)
+            }
+            &InvalidUniformType { ref name, expected } => {
+                write!(f, "uniform `{}` is of type {:?}", name, expected)
+            }
+            &InvalidIndex { index, vertices } => {
+                write!(f, "index {} is out of range for {} vertices", index, vertices)
+            }
+            &InvalidRange { ref range, count } => {
+                write!(f, "range {:?} is out of bounds for {} elements", range, count)
+            }
+            &InvalidInstanceCount { instances, available } => {
+                write!(f, "{} instances drawn but only {} are in the buffers", instances, available)
+            }
+            &InvalidLayout(ref reason) => write!(f, "invalid vertex layout: {}", reason),
+            &InvalidTextureData { expected, actual } => {
+                write!(f,
+                       "texture data is {} bytes long, expected {}",
+                       actual,
+                       expected)
+            }
+            &InvalidUniformBlock { ref name } => {
+                write!(f, "the program has no active uniform block `{}`", name)
+            }
+            &InvalidFramebuffer(ref reason) => write!(f, "invalid framebuffer: {}", reason),
+            &InvalidBufferSize { expected, actual } => {
+                write!(f, "buffer is {} bytes long, expected {}", actual, expected)
+            }
+            &InvalidName(ref name) => write!(f, "`{}` contains a NUL byte", name),
+            &Unsupported(ref what) => write!(f, "unsupported by this backend: {}", what),
+        }
+    }
+}
+
+impl Error for BackendError {
+    fn description(&self) -> &str {
+        use self::BackendError::*;
+        match self {
+            &ContextCreation(..) => "context creation failed",
+            &SwapBuffers(..) => "swapping buffers failed",
+            &ShaderCompile { .. } => "shader compilation failed",
+            &Link { .. } => "program linking failed",
+            &InvalidAttribute { .. } => "invalid vertex attribute",
+            &InvalidAttributeSize { .. } => "vertex attribute size mismatch",
+            &InvalidAttributeType { .. } => "vertex attribute type mismatch",
+            &InvalidUniformType { .. } => "uniform type mismatch",
+            &InvalidIndex { .. } => "index out of range",
+            &InvalidRange { .. } => "draw range out of bounds",
+            &InvalidInstanceCount { .. } => "instance count out of range",
+            &InvalidLayout(..) => "invalid vertex layout",
+            &InvalidTextureData { .. } => "invalid texture data",
+            &InvalidUniformBlock { .. } => "invalid uniform block",
+            &InvalidFramebuffer(..) => "invalid framebuffer",
+            &InvalidBufferSize { .. } => "invalid buffer size",
+            &InvalidName(..) => "name contains a NUL byte",
+            &Unsupported(..) => "unsupported operation",
+        }
+    }
+}
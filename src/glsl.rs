@@ -0,0 +1,146 @@
+use super::backend::{DataType, ProgramInput};
+
+const IGNORED_QUALIFIERS: &'static [&'static str] = &["flat", "smooth", "noperspective",
+                                                       "centroid", "invariant", "highp",
+                                                       "mediump", "lowp"];
+
+/// Scans GLSL source for global declarations using one of `qualifiers`
+/// (e.g. `["in", "attribute"]` or `["uniform"]`). Used by the backends
+/// that do not compile GLSL to reflect over a program. Locations are taken
+/// from `layout(location = N)` or else numbered in declaration order.
+pub fn declarations(src: &str, qualifiers: &[&str]) -> Vec<ProgramInput> {
+    let mut inputs = Vec::new();
+    let src = strip_source(src);
+    for statement in src.split(|c| c == ';' || c == '{' || c == '}') {
+        let (location, statement) = split_layout(statement.trim());
+        let mut tokens = statement.split_whitespace()
+            .skip_while(|t| IGNORED_QUALIFIERS.contains(t));
+        match tokens.next() {
+            Some(q) if qualifiers.contains(&q) => (),
+            _ => continue,
+        }
+        let ty = match tokens.next() {
+            Some(t) => DataType::from_glsl(t),
+            None => continue,
+        };
+        let rest = tokens.collect::<Vec<_>>().join("");
+        for declarator in rest.split(',').filter(|d| !d.is_empty()) {
+            let (name, size) = match declarator.find('[') {
+                Some(i) => {
+                    let size = declarator[i + 1..].trim_end_matches(']').parse().unwrap_or(1);
+                    (&declarator[..i], size)
+                }
+                None => (declarator, 1),
+            };
+            let index = inputs.len() as i32;
+            inputs.push(ProgramInput {
+                name: String::from(name),
+                ty: ty,
+                size: size,
+                location: location.unwrap_or(index),
+            });
+        }
+    }
+    inputs
+}
+
+/// Reflects a program from its sources: attributes are the vertex shader's
+/// inputs, uniforms are merged over all stages and arrays are named
+/// `name[0]` as GL reports them.
+pub fn reflect(vssrc: &str,
+               fssrc: &str,
+               gssrc: Option<&str>)
+               -> (Vec<ProgramInput>, Vec<ProgramInput>) {
+    let attributes = declarations(vssrc, &["in", "attribute"]);
+    let mut uniforms: Vec<ProgramInput> = Vec::new();
+    for src in [Some(vssrc), Some(fssrc), gssrc].iter().filter_map(|s| *s) {
+        for mut u in declarations(src, &["uniform"]) {
+            if u.size > 1 {
+                u.name.push_str("[0]");
+            }
+            if !uniforms.iter().any(|v| v.name == u.name) {
+                u.location = uniforms.len() as i32;
+                uniforms.push(u);
+            }
+        }
+    }
+    (attributes, uniforms)
+}
+
+/// Names of the uniform blocks (`uniform Camera { ... };`) declared in the
+/// sources of a program.
+pub fn uniform_blocks(vssrc: &str, fssrc: &str, gssrc: Option<&str>) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    for src in [Some(vssrc), Some(fssrc), gssrc].iter().filter_map(|s| *s) {
+        let src = strip_source(src);
+        let mut pieces = src.split('{').collect::<Vec<_>>();
+        pieces.pop();
+        for piece in pieces {
+            let start = piece.rfind(|c| c == ';' || c == '}').map_or(0, |i| i + 1);
+            let (_, statement) = split_layout(piece[start..].trim());
+            let tokens = statement.split_whitespace().collect::<Vec<_>>();
+            if tokens.len() == 2 && tokens[0] == "uniform" &&
+               !blocks.iter().any(|b| b == tokens[1]) {
+                blocks.push(String::from(tokens[1]));
+            }
+        }
+    }
+    blocks
+}
+
+/// Removes comments and preprocessor lines.
+fn strip_source(src: &str) -> String {
+    strip_comments(src)
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn strip_comments(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    loop {
+        let start = match (rest.find("//"), rest.find("/*")) {
+            (Some(l), Some(b)) => l.min(b),
+            (Some(l), None) => l,
+            (None, Some(b)) => b,
+            (None, None) => break,
+        };
+        let end = if rest[start..].starts_with("//") {
+            rest[start..].find('\n').map(|e| start + e)
+        } else {
+            rest[start..].find("*/").map(|e| start + e + 2)
+        };
+        out.push_str(&rest[..start]);
+        out.push(' ');
+        rest = match end {
+            Some(end) => &rest[end..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Splits a leading `layout(...)` off `statement`, returning its location.
+fn split_layout(statement: &str) -> (Option<i32>, &str) {
+    if !statement.starts_with("layout") {
+        return (None, statement);
+    }
+    let close = match statement.find(')') {
+        Some(i) => i,
+        None => return (None, statement),
+    };
+    let args = &statement[statement.find('(').map_or(close, |i| i + 1)..close];
+    let location = args.split(',')
+        .filter_map(|arg| {
+            let mut kv = arg.split('=').map(|s| s.trim());
+            match (kv.next(), kv.next()) {
+                (Some("location"), Some(v)) => v.parse().ok(),
+                _ => None,
+            }
+        })
+        .next();
+    (location, statement[close + 1..].trim())
+}
@@ -1,6 +1,9 @@
 
 extern crate glutin;
 extern crate gl;
+extern crate image;
+#[cfg(target_os = "linux")]
+extern crate shared_library;
 
 pub mod event {
     pub use glutin::VirtualKeyCode as KeyCode;
@@ -14,5 +17,30 @@ pub use event::*;
 pub mod backend;
 pub use backend::*;
 
+pub mod diagnostics;
+
+pub mod glsl;
+
+#[macro_use]
+pub mod std140;
+pub use std140::{Std140, Padded};
+
+pub mod parameters;
+pub use parameters::*;
+
+pub mod error;
+pub use error::BackendError;
+
+#[cfg(target_os = "linux")]
+mod egl;
+
 pub mod opengl;
-pub use opengl::OpenGL;
+pub use opengl::{OpenGL, OpenGLBuilder};
+
+pub mod software;
+pub use software::Software;
+
+pub mod recording;
+pub use recording::Recorder;
+
+pub mod testing;
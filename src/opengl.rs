@@ -1,26 +1,210 @@
 
 use gl;
 
-use glutin::{Window, WindowBuilder, GlRequest, GlProfile, Api};
+use glutin::{self, Window, WindowBuilder, HeadlessRendererBuilder, HeadlessContext, GlRequest,
+             Api};
+pub use glutin::GlProfile;
 
 use gl::types::*;
-use std::mem;
+use image::{self, RgbaImage};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ptr;
-use std::str;
 use std::ffi::CString;
-use std::ops::Drop;
+use std::ops::{Drop, Range};
 use std::rc::Rc;
 
 use super::backend::*;
+use super::diagnostics;
+#[cfg(target_os = "linux")]
+use super::egl::EglContext;
+use super::error::*;
 use super::event::*;
+use super::parameters::*;
 
 pub struct OpenGL {
     context: Rc<GLContext>,
 }
 
 impl OpenGL {
-    pub fn new() -> OpenGL {
-        OpenGL { context: Rc::new(GLContext::new()) }
+    pub fn new() -> Result<OpenGL, BackendError> {
+        OpenGLBuilder::new().build()
+    }
+    /// Creates a context without a window that renders into an off-screen
+    /// framebuffer of the given size (OSMesa, or EGL's surfaceless platform on
+    /// Linux, e.g. Mesa llvmpipe).
+    pub fn headless(width: u32, height: u32) -> Result<OpenGL, BackendError> {
+        OpenGLBuilder::new().with_dimensions(width, height).build_headless()
+    }
+    /// Resizes the off-screen framebuffer of a headless context, which gets
+    /// no `Event::Resized`. Its contents are lost.
+    pub fn resize(&self, width: u32, height: u32) {
+        self.context.resize(width, height);
+    }
+}
+
+pub struct OpenGLBuilder {
+    title: String,
+    dimensions: Option<(u32, u32)>,
+    versions: Vec<(u8, u8, GlProfile)>,
+    vsync: bool,
+    multisampling: u16,
+    depth_bits: Option<u8>,
+    stencil_bits: Option<u8>,
+    srgb: Option<bool>,
+    visible: bool,
+    fullscreen: bool,
+}
+
+impl OpenGLBuilder {
+    pub fn new() -> OpenGLBuilder {
+        OpenGLBuilder {
+            title: String::from("graphic_backend"),
+            dimensions: None,
+            versions: Vec::new(),
+            vsync: false,
+            multisampling: 0,
+            depth_bits: None,
+            stencil_bits: None,
+            srgb: None,
+            visible: true,
+            fullscreen: false,
+        }
+    }
+    pub fn with_title(mut self, title: &str) -> OpenGLBuilder {
+        self.title = String::from(title);
+        self
+    }
+    pub fn with_dimensions(mut self, width: u32, height: u32) -> OpenGLBuilder {
+        self.dimensions = Some((width, height));
+        self
+    }
+    /// Requests a GL version. Each call adds a fallback that is tried, in
+    /// call order, when the previous ones fail; 3.2 core is used when none
+    /// is given.
+    pub fn with_gl_version(mut self, major: u8, minor: u8, profile: GlProfile) -> OpenGLBuilder {
+        self.versions.push((major, minor, profile));
+        self
+    }
+    pub fn with_vsync(mut self, vsync: bool) -> OpenGLBuilder {
+        self.vsync = vsync;
+        self
+    }
+    /// `samples` must be a power of two, or else `build` fails; 0 disables
+    /// multisampling.
+    pub fn with_multisampling(mut self, samples: u16) -> OpenGLBuilder {
+        self.multisampling = samples;
+        self
+    }
+    pub fn with_depth_buffer(mut self, bits: u8) -> OpenGLBuilder {
+        self.depth_bits = Some(bits);
+        self
+    }
+    pub fn with_stencil_buffer(mut self, bits: u8) -> OpenGLBuilder {
+        self.stencil_bits = Some(bits);
+        self
+    }
+    pub fn with_srgb(mut self, srgb: bool) -> OpenGLBuilder {
+        self.srgb = Some(srgb);
+        self
+    }
+    pub fn with_visibility(mut self, visible: bool) -> OpenGLBuilder {
+        self.visible = visible;
+        self
+    }
+    /// Goes fullscreen on the primary monitor.
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> OpenGLBuilder {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn build(self) -> Result<OpenGL, BackendError> {
+        // glutin panics on other counts
+        if self.multisampling != 0 && !self.multisampling.is_power_of_two() {
+            return Err(BackendError::ContextCreation(format!("{} samples is not a power of two",
+                                                             self.multisampling)));
+        }
+        let mut errors = Vec::new();
+        for &(major, minor, profile) in self.gl_versions().iter() {
+            match self.window_builder(major, minor, profile).build() {
+                Ok(window) => {
+                    let context = try!(GLContext::from_window(window));
+                    if self.srgb == Some(true) {
+                        unsafe {
+                            gl::Enable(gl::FRAMEBUFFER_SRGB);
+                        }
+                    }
+                    return Ok(OpenGL { context: Rc::new(context) });
+                }
+                Err(e) => errors.push(format!("{}.{} {:?}: {}", major, minor, profile, e)),
+            }
+        }
+        Err(BackendError::ContextCreation(errors.join("; ")))
+    }
+
+    /// Builds a context without a window; only the dimensions (640x480 by
+    /// default) and the GL versions apply.
+    pub fn build_headless(self) -> Result<OpenGL, BackendError> {
+        let (width, height) = self.dimensions.unwrap_or((640, 480));
+        let mut errors = Vec::new();
+        for &(major, minor, profile) in self.gl_versions().iter() {
+            let built = HeadlessRendererBuilder::new(width, height)
+                .with_gl(GlRequest::Specific(Api::OpenGl, (major, minor)))
+                .with_gl_profile(profile)
+                .build();
+            match built {
+                Ok(headless) => {
+                    let context = try!(GLContext::from_headless(headless, width, height));
+                    return Ok(OpenGL { context: Rc::new(context) });
+                }
+                Err(e) => errors.push(format!("{}.{} {:?}: {}", major, minor, profile, e)),
+            }
+            #[cfg(target_os = "linux")]
+            match EglContext::new(major, minor, profile) {
+                Ok(egl) => {
+                    let context = try!(GLContext::from_egl(egl, width, height));
+                    return Ok(OpenGL { context: Rc::new(context) });
+                }
+                Err(e) => errors.push(format!("{}.{} {:?} (EGL): {}", major, minor, profile, e)),
+            }
+        }
+        Err(BackendError::ContextCreation(errors.join("; ")))
+    }
+
+    fn gl_versions(&self) -> Vec<(u8, u8, GlProfile)> {
+        if self.versions.is_empty() {
+            vec![(3, 2, GlProfile::Core)]
+        } else {
+            self.versions.clone()
+        }
+    }
+
+    fn window_builder(&self, major: u8, minor: u8, profile: GlProfile) -> WindowBuilder<'static> {
+        let mut builder = WindowBuilder::new()
+            .with_title(self.title.clone())
+            .with_gl(GlRequest::Specific(Api::OpenGl, (major, minor)))
+            .with_gl_profile(profile)
+            .with_visibility(self.visible)
+            .with_srgb(self.srgb);
+        if let Some((width, height)) = self.dimensions {
+            builder = builder.with_dimensions(width, height);
+        }
+        if self.vsync {
+            builder = builder.with_vsync();
+        }
+        if self.multisampling > 0 {
+            builder = builder.with_multisampling(self.multisampling);
+        }
+        if let Some(bits) = self.depth_bits {
+            builder = builder.with_depth_buffer(bits);
+        }
+        if let Some(bits) = self.stencil_bits {
+            builder = builder.with_stencil_buffer(bits);
+        }
+        if self.fullscreen {
+            builder = builder.with_fullscreen(glutin::get_primary_monitor());
+        }
+        builder
     }
 }
 
@@ -30,76 +214,527 @@ impl_facade!(OpenGL, context, {
     Program => GLProgram,
     VertexBufferBuilder => GLVertexBufferBuilder,
     Texture2D => GLTexture2D,
+    UniformBuffer => GLUniformBuffer,
+    Framebuffer => GLFramebuffer,
 });
 
+pub enum GLSurface {
+    Window(Window),
+    Headless(HeadlessContext),
+    #[cfg(target_os = "linux")]
+    Egl(EglContext),
+}
+
 pub struct GLContext {
-    pub window: Window,
+    pub surface: GLSurface,
+    framebuffer: u32,
+    renderbuffers: [u32; 2],
+    /// The size of the window, updated as it is resized, or of the
+    /// off-screen framebuffer.
+    size: Cell<(u32, u32)>,
+    /// The size of the framebuffer the last bound frame draws into.
+    target_size: Cell<(u32, u32)>,
+    viewport: Cell<Rect>,
+    /// The generation of each uniform buffer alive, so that draws skip the
+    /// blocks whose buffer was dropped even once GL reuses its name.
+    uniform_buffers: RefCell<HashMap<u32, u64>>,
+    uniform_buffer_generation: Cell<u64>,
+    /// The fixed-function state GL is in, so that draws only change what
+    /// differs.
+    parameters: RefCell<DrawParameters>,
 }
 
 impl GLContext {
-    pub fn new() -> GLContext {
-        let window = WindowBuilder::new()
-            .with_gl(GlRequest::Specific(Api::OpenGl, (3, 2)))
-            .with_gl_profile(GlProfile::Core)
-            .build()
-            .unwrap();
+    pub fn from_window(window: Window) -> Result<GLContext, BackendError> {
         unsafe {
-            window.make_current().unwrap();
+            try!(window.make_current().map_err(|e| BackendError::ContextCreation(e.to_string())));
             gl::load_with(|s| window.get_proc_address(s) as *const _);
+            // let vertex shaders writing `gl_PointSize` override `point_size`
+            gl::Enable(gl::PROGRAM_POINT_SIZE);
+        }
+        // GL starts with the viewport covering the window
+        let size = window.get_inner_size_pixels().unwrap_or((0, 0));
+        Ok(GLContext {
+            surface: GLSurface::Window(window),
+            framebuffer: 0,
+            renderbuffers: [0, 0],
+            size: Cell::new(size),
+            target_size: Cell::new(size),
+            viewport: Cell::new(Rect::full(size)),
+            uniform_buffers: RefCell::new(HashMap::new()),
+            uniform_buffer_generation: Cell::new(0),
+            parameters: RefCell::new(DrawParameters::default()),
+        })
+    }
+    pub fn from_headless(context: HeadlessContext,
+                         width: u32,
+                         height: u32)
+                         -> Result<GLContext, BackendError> {
+        unsafe {
+            try!(context.make_current().map_err(|e| BackendError::ContextCreation(e.to_string())));
+            gl::load_with(|s| context.get_proc_address(s) as *const _);
         }
-        GLContext { window: window }
+        GLContext::offscreen(GLSurface::Headless(context), width, height)
     }
+    #[cfg(target_os = "linux")]
+    pub fn from_egl(context: EglContext,
+                    width: u32,
+                    height: u32)
+                    -> Result<GLContext, BackendError> {
+        unsafe {
+            try!(context.make_current().map_err(BackendError::ContextCreation));
+            gl::load_with(|s| context.get_proc_address(s) as *const _);
+        }
+        GLContext::offscreen(GLSurface::Egl(context), width, height)
+    }
+    /// Sets up the framebuffer a context without a window draws into, once
+    /// it is current.
+    fn offscreen(surface: GLSurface,
+                 width: u32,
+                 height: u32)
+                 -> Result<GLContext, BackendError> {
+        let mut framebuffer: u32 = 0;
+        let mut renderbuffers: [u32; 2] = [0, 0];
+        unsafe {
+            gl::Enable(gl::PROGRAM_POINT_SIZE);
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+            allocate_renderbuffers(renderbuffers, width, height);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER,
+                                        gl::COLOR_ATTACHMENT0,
+                                        gl::RENDERBUFFER,
+                                        renderbuffers[0]);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER,
+                                        gl::DEPTH_STENCIL_ATTACHMENT,
+                                        gl::RENDERBUFFER,
+                                        renderbuffers[1]);
+            gl::Viewport(0, 0, width as i32, height as i32);
+        }
+        let context = GLContext {
+            surface: surface,
+            framebuffer: framebuffer,
+            renderbuffers: renderbuffers,
+            size: Cell::new((width, height)),
+            target_size: Cell::new((width, height)),
+            viewport: Cell::new(Rect::full((width, height))),
+            uniform_buffers: RefCell::new(HashMap::new()),
+            uniform_buffer_generation: Cell::new(0),
+            parameters: RefCell::new(DrawParameters::default()),
+        };
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(BackendError::ContextCreation(format!("off-screen framebuffer is \
+                                                              incomplete (status {:#x})",
+                                                             status)));
+        }
+        Ok(context)
+    }
+    /// Follows a new window size, or reallocates the off-screen framebuffer,
+    /// discarding its contents. Frames bound afterwards cover the new size.
+    fn resize(&self, width: u32, height: u32) {
+        if self.framebuffer != 0 {
+            unsafe { allocate_renderbuffers(self.renderbuffers, width, height) };
+        }
+        self.size.set((width, height));
+    }
+}
+
+/// Gives the color and depth-stencil renderbuffers of an off-screen
+/// framebuffer storage for `width` by `height` pixels.
+unsafe fn allocate_renderbuffers(renderbuffers: [u32; 2], width: u32, height: u32) {
+    gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+    gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+    gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+    gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
 }
 
 impl Context for GLContext {
     fn get_events(&self) -> Vec<Event> {
         let mut es = Vec::new();
-        for e in self.window.poll_events() {
-            es.push(e);
+        if let GLSurface::Window(ref window) = self.surface {
+            for e in window.poll_events() {
+                if let Event::Resized(width, height) = e {
+                    self.resize(width, height);
+                }
+                es.push(e);
+            }
         }
         es
     }
-    fn finish(&self) {
-        self.window.swap_buffers().unwrap();
+    fn dimensions(&self) -> (u32, u32) {
+        self.size.get()
+    }
+    fn read_pixels(&self) -> RgbaImage {
+        self.read_buffer(gl::FRONT)
+    }
+    fn finish(&self) -> Result<(), BackendError> {
+        match self.surface {
+            GLSurface::Window(ref window) => {
+                window.swap_buffers().map_err(|e| BackendError::SwapBuffers(e.to_string()))
+            }
+            _ => {
+                unsafe { gl::Finish() };
+                Ok(())
+            }
+        }
+    }
+}
+
+impl GLContext {
+    /// Reads `buffer` of the default framebuffer, or the color attachment when
+    /// rendering off-screen, flipping GL's bottom-up rows.
+    fn read_buffer(&self, buffer: GLenum) -> RgbaImage {
+        read_framebuffer(self.framebuffer, buffer, self.dimensions())
+    }
+}
+
+/// Reads `buffer` of `framebuffer` if it is the default one, or else its
+/// first color attachment, top row first.
+fn read_framebuffer(framebuffer: u32, buffer: GLenum, (width, height): (u32, u32)) -> RgbaImage {
+    let mut data: Vec<u8> = vec![0; (width * height * 4) as usize];
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        if framebuffer == 0 {
+            gl::ReadBuffer(buffer);
+        } else {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0,
+                       0,
+                       width as i32,
+                       height as i32,
+                       gl::RGBA,
+                       gl::UNSIGNED_BYTE,
+                       data.as_mut_ptr() as *mut _);
+    }
+    let img = RgbaImage::from_raw(width, height, data).unwrap();
+    image::imageops::flip_vertical(&img)
+}
+
+impl GLContext {
+    /// Brings GL's fixed-function state to `params`, skipping the calls for
+    /// state that is already set.
+    fn apply_parameters(&self, params: &DrawParameters) {
+        let viewport = params.viewport.unwrap_or(Rect::full(self.target_size.get()));
+        if self.viewport.get() != viewport {
+            unsafe {
+                gl::Viewport(viewport.left as i32,
+                             viewport.bottom as i32,
+                             viewport.width as i32,
+                             viewport.height as i32);
+            }
+            self.viewport.set(viewport);
+        }
+        let mut current = self.parameters.borrow_mut();
+        if *current == *params {
+            return;
+        }
+        unsafe {
+            if current.depth != params.depth {
+                set_capability(gl::DEPTH_TEST, params.depth.is_enabled());
+                gl::DepthFunc(comparison_to_gl_type(params.depth.test));
+                gl::DepthMask(params.depth.write as GLboolean);
+            }
+            if current.blend != params.blend {
+                set_capability(gl::BLEND, params.blend.is_some());
+                if let Some(blend) = params.blend {
+                    gl::BlendEquationSeparate(blend_op_to_gl_type(blend.color.op),
+                                              blend_op_to_gl_type(blend.alpha.op));
+                    gl::BlendFuncSeparate(blend_factor_to_gl_type(blend.color.source),
+                                          blend_factor_to_gl_type(blend.color.destination),
+                                          blend_factor_to_gl_type(blend.alpha.source),
+                                          blend_factor_to_gl_type(blend.alpha.destination));
+                    let [r, g, b, a] = blend.constant;
+                    gl::BlendColor(r, g, b, a);
+                }
+            }
+            if current.cull != params.cull {
+                set_capability(gl::CULL_FACE, params.cull != Cull::None);
+                match params.cull {
+                    Cull::None => (),
+                    Cull::Front => gl::CullFace(gl::FRONT),
+                    Cull::Back => gl::CullFace(gl::BACK),
+                    Cull::FrontAndBack => gl::CullFace(gl::FRONT_AND_BACK),
+                }
+            }
+            if current.front_face != params.front_face {
+                gl::FrontFace(match params.front_face {
+                    Winding::Clockwise => gl::CW,
+                    Winding::CounterClockwise => gl::CCW,
+                });
+            }
+            if current.stencil != params.stencil {
+                set_capability(gl::STENCIL_TEST, params.stencil.is_some());
+                if let Some(stencil) = params.stencil {
+                    gl::StencilFunc(comparison_to_gl_type(stencil.test),
+                                    stencil.reference as GLint,
+                                    stencil.read_mask as GLuint);
+                    gl::StencilMask(stencil.write_mask as GLuint);
+                    gl::StencilOp(stencil_op_to_gl_type(stencil.fail),
+                                  stencil_op_to_gl_type(stencil.depth_fail),
+                                  stencil_op_to_gl_type(stencil.pass));
+                }
+            }
+            if current.color_mask != params.color_mask {
+                let [r, g, b, a] = params.color_mask;
+                gl::ColorMask(r as GLboolean, g as GLboolean, b as GLboolean, a as GLboolean);
+            }
+            if current.polygon_offset != params.polygon_offset {
+                let enabled = params.polygon_offset.is_some();
+                set_capability(gl::POLYGON_OFFSET_FILL, enabled);
+                set_capability(gl::POLYGON_OFFSET_LINE, enabled);
+                set_capability(gl::POLYGON_OFFSET_POINT, enabled);
+                if let Some(offset) = params.polygon_offset {
+                    gl::PolygonOffset(offset.factor, offset.units);
+                }
+            }
+            if current.scissor != params.scissor {
+                set_capability(gl::SCISSOR_TEST, params.scissor.is_some());
+                if let Some(scissor) = params.scissor {
+                    gl::Scissor(scissor.left as i32,
+                                scissor.bottom as i32,
+                                scissor.width as i32,
+                                scissor.height as i32);
+                }
+            }
+            if current.polygon_mode != params.polygon_mode {
+                gl::PolygonMode(gl::FRONT_AND_BACK,
+                                match params.polygon_mode {
+                                    PolygonMode::Fill => gl::FILL,
+                                    PolygonMode::Line => gl::LINE,
+                                    PolygonMode::Point => gl::POINT,
+                                });
+            }
+        }
+        *current = *params;
+    }
+}
+
+impl GLContext {
+    /// Clears the bound framebuffer. The write masks and scissor of the
+    /// last draw would restrict the clear too, so they are lifted meanwhile.
+    fn clear(&self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        let params = self.parameters.borrow();
+        let mut mask = 0;
+        unsafe {
+            if let Some([r, g, b, a]) = color {
+                gl::ClearColor(r, g, b, a);
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                mask |= gl::COLOR_BUFFER_BIT;
+            }
+            if let Some(depth) = depth {
+                gl::ClearDepth(depth as f64);
+                gl::DepthMask(gl::TRUE);
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if let Some(stencil) = stencil {
+                gl::ClearStencil(stencil as GLint);
+                gl::StencilMask(0xff);
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+            if mask == 0 {
+                return;
+            }
+            if params.scissor.is_some() {
+                gl::Disable(gl::SCISSOR_TEST);
+            }
+            gl::Clear(mask);
+            if params.scissor.is_some() {
+                gl::Enable(gl::SCISSOR_TEST);
+            }
+            let [r, g, b, a] = params.color_mask;
+            gl::ColorMask(r as GLboolean, g as GLboolean, b as GLboolean, a as GLboolean);
+            gl::DepthMask(params.depth.write as GLboolean);
+            if let Some(stencil) = params.stencil {
+                gl::StencilMask(stencil.write_mask as GLuint);
+            }
+        }
+    }
+}
+
+unsafe fn set_capability(capability: GLenum, enabled: bool) {
+    if enabled {
+        gl::Enable(capability);
+    } else {
+        gl::Disable(capability);
+    }
+}
+
+pub fn comparison_to_gl_type(comparison: Comparison) -> GLenum {
+    use Comparison::*;
+    match comparison {
+        Never => gl::NEVER,
+        Less => gl::LESS,
+        Equal => gl::EQUAL,
+        LessOrEqual => gl::LEQUAL,
+        Greater => gl::GREATER,
+        NotEqual => gl::NOTEQUAL,
+        GreaterOrEqual => gl::GEQUAL,
+        Always => gl::ALWAYS,
+    }
+}
+
+pub fn blend_op_to_gl_type(op: BlendOp) -> GLenum {
+    use BlendOp::*;
+    match op {
+        Add => gl::FUNC_ADD,
+        Subtract => gl::FUNC_SUBTRACT,
+        ReverseSubtract => gl::FUNC_REVERSE_SUBTRACT,
+        Min => gl::MIN,
+        Max => gl::MAX,
+    }
+}
+
+pub fn blend_factor_to_gl_type(factor: BlendFactor) -> GLenum {
+    use BlendFactor::*;
+    match factor {
+        Zero => gl::ZERO,
+        One => gl::ONE,
+        SourceColor => gl::SRC_COLOR,
+        OneMinusSourceColor => gl::ONE_MINUS_SRC_COLOR,
+        DestinationColor => gl::DST_COLOR,
+        OneMinusDestinationColor => gl::ONE_MINUS_DST_COLOR,
+        SourceAlpha => gl::SRC_ALPHA,
+        OneMinusSourceAlpha => gl::ONE_MINUS_SRC_ALPHA,
+        DestinationAlpha => gl::DST_ALPHA,
+        OneMinusDestinationAlpha => gl::ONE_MINUS_DST_ALPHA,
+        ConstantColor => gl::CONSTANT_COLOR,
+        OneMinusConstantColor => gl::ONE_MINUS_CONSTANT_COLOR,
+        ConstantAlpha => gl::CONSTANT_ALPHA,
+        OneMinusConstantAlpha => gl::ONE_MINUS_CONSTANT_ALPHA,
+        SourceAlphaSaturate => gl::SRC_ALPHA_SATURATE,
+    }
+}
+
+pub fn stencil_op_to_gl_type(op: StencilOp) -> GLenum {
+    use StencilOp::*;
+    match op {
+        Keep => gl::KEEP,
+        Zero => gl::ZERO,
+        Replace => gl::REPLACE,
+        Increment => gl::INCR,
+        IncrementWrap => gl::INCR_WRAP,
+        Decrement => gl::DECR,
+        DecrementWrap => gl::DECR_WRAP,
+        Invert => gl::INVERT,
+    }
+}
+
+impl Drop for GLContext {
+    fn drop(&mut self) {
+        if self.framebuffer != 0 {
+            unsafe {
+                gl::DeleteFramebuffers(1, &self.framebuffer);
+                gl::DeleteRenderbuffers(2, self.renderbuffers.as_ptr());
+            }
+        }
     }
 }
 
 pub struct GLFrame {
     context: Rc<GLContext>,
+    /// A `GLFramebuffer` drawn into instead of the context's framebuffer.
+    target: Option<u32>,
+    /// The size of `target`.
+    size: (u32, u32),
 }
 
 impl GLFrame {
     pub fn new(context: Rc<GLContext>) -> GLFrame {
-        GLFrame { context: context }
+        let size = context.dimensions();
+        GLFrame::with_target(context, None, size)
+    }
+    fn with_target(context: Rc<GLContext>, target: Option<u32>, size: (u32, u32)) -> GLFrame {
+        let frame = GLFrame {
+            context: context,
+            target: target,
+            size: size,
+        };
+        frame.bind();
+        frame
+    }
+    /// Makes draws and clears go to this frame, which other frames may
+    /// have changed.
+    fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.target.unwrap_or(self.context.framebuffer));
+        }
+        self.context.target_size.set(self.dimensions());
     }
 }
 
 impl Frame for GLFrame {
-    fn draw(&mut self,
-            program: &BProgram,
-            draw_type: DrawType,
-            vb: &BVertexBuffer,
-            uniforms: &Uniforms<u32>) {
+    fn multi_draw_instanced(&mut self,
+                            program: &BProgram,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        self.bind();
+        program.multi_draw_instanced(draw_type, vb, ranges, instances, uniforms, params)
+    }
+    fn clear(&mut self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        self.bind();
+        self.context.clear(color, depth, stencil);
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        match self.target {
+            Some(..) => self.size,
+            None => self.context.dimensions(),
+        }
+    }
+    fn line_width(&self, width: f32) {
         unsafe {
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::LineWidth(width);
         }
-        program.draw(draw_type, vb, uniforms);
     }
-    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+    fn point_size(&self, size: f32) {
         unsafe {
-            gl::ClearColor(r, g, b, a);
+            gl::PointSize(size);
         }
     }
-    fn finish(self: Box<Self>) {
-        self.context.finish();
+    fn read_pixels(&self) -> RgbaImage {
+        match self.target {
+            Some(framebuffer) => read_framebuffer(framebuffer, gl::COLOR_ATTACHMENT0, self.size),
+            None => self.context.read_buffer(gl::BACK),
+        }
+    }
+    fn finish(self: Box<Self>) -> Result<(), BackendError> {
+        match self.target {
+            Some(..) => {
+                unsafe { gl::Flush() };
+                Ok(())
+            }
+            None => self.context.finish(),
+        }
     }
 }
 
-pub fn compile_shader(src: &str, ty: GLenum) -> Result<u32, String> {
+pub fn shader_stage_to_gl_type(stage: ShaderStage) -> GLenum {
+    use ShaderStage::*;
+    match stage {
+        Vertex => gl::VERTEX_SHADER,
+        Fragment => gl::FRAGMENT_SHADER,
+        Geometry => gl::GEOMETRY_SHADER,
+    }
+}
+
+pub fn compile_shader(src: &str, stage: ShaderStage) -> Result<u32, BackendError> {
+    let c_str = try!(CString::new(src.as_bytes()).map_err(|_| {
+        BackendError::ShaderCompile {
+            stage: stage,
+            log: String::from("shader source contains a NUL byte"),
+            diagnostics: Vec::new(),
+        }
+    }));
     let shader;
     unsafe {
-        shader = gl::CreateShader(ty);
-        let c_str = CString::new(src.as_bytes()).unwrap();
+        shader = gl::CreateShader(shader_stage_to_gl_type(stage));
         gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
@@ -109,15 +744,18 @@ pub fn compile_shader(src: &str, ty: GLenum) -> Result<u32, String> {
         if status != (gl::TRUE as GLint) {
             let mut len = 0;
             gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len((len as usize) - 1);
+            let mut buf: Vec<u8> = vec![0; len as usize];
             gl::GetShaderInfoLog(shader,
                                  len,
                                  ptr::null_mut(),
                                  buf.as_mut_ptr() as *mut GLchar);
-            return Err(String::from(str::from_utf8(&buf)
-                .ok()
-                .expect("ShaderInfoLog not valid utf8")));
+            gl::DeleteShader(shader);
+            let log = info_log_to_string(buf);
+            return Err(BackendError::ShaderCompile {
+                stage: stage,
+                diagnostics: diagnostics::parse_log(&log, src),
+                log: log,
+            });
         }
     }
     Ok(shader)
@@ -133,7 +771,7 @@ pub fn attach_shader(program: u32, shader: u32) {
     }
 }
 
-pub fn link_program(program: u32) -> Result<u32, String> {
+pub fn link_program(program: u32) -> Result<u32, BackendError> {
     unsafe {
         gl::LinkProgram(program);
         let mut status = gl::FALSE as GLint;
@@ -142,119 +780,417 @@ pub fn link_program(program: u32) -> Result<u32, String> {
         if status != (gl::TRUE as GLint) {
             let mut len: GLint = 0;
             gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len((len as usize) - 1);
+            let mut buf: Vec<u8> = vec![0; len as usize];
             gl::GetProgramInfoLog(program,
                                   len,
                                   ptr::null_mut(),
                                   buf.as_mut_ptr() as *mut GLchar);
-            return Err(String::from(str::from_utf8(&buf)
-                .ok()
-                .expect("ProgramInfoLog not valid utf8")));
+            return Err(BackendError::Link { log: info_log_to_string(buf) });
         }
     }
     Ok(program)
 }
 
+fn info_log_to_string(mut buf: Vec<u8>) -> String {
+    // drop the terminating NUL
+    while buf.last() == Some(&0) {
+        buf.pop();
+    }
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+pub fn c_string(name: &str) -> Result<CString, BackendError> {
+    CString::new(name.as_bytes()).map_err(|_| BackendError::InvalidName(String::from(name)))
+}
+
 pub struct GLProgram {
+    context: Rc<GLContext>,
     program: u32,
+    attributes: Vec<ProgramInput>,
+    uniforms: Vec<ProgramInput>,
+    uniform_warnings: UniformWarnings,
+    /// Uniform locations by name, seeded from reflection and filled in as
+    /// element and member names like `lights[2]` are first used.
+    locations: RefCell<HashMap<String, i32>>,
+    /// The buffer bound to each uniform block and its generation, by block
+    /// index.
+    block_bindings: RefCell<Vec<(u32, u32, u64)>>,
 }
 
 impl GLProgram {
-    fn from_source(vssrc: &str,
+    fn from_source(context: &Rc<GLContext>,
+                   vssrc: &str,
                    fssrc: &str,
                    gssrc: Option<&str>,
                    out: &str)
-                   -> Result<GLProgram, String> {
-        let program = create_program();
+                   -> Result<GLProgram, BackendError> {
+        // owned from the start so that it is deleted on every error path
+        let mut program = GLProgram {
+            context: context.clone(),
+            program: create_program(),
+            attributes: Vec::new(),
+            uniforms: Vec::new(),
+            uniform_warnings: UniformWarnings::new(),
+            locations: RefCell::new(HashMap::new()),
+            block_bindings: RefCell::new(Vec::new()),
+        };
 
-        let vs = try!(compile_shader(vssrc, gl::VERTEX_SHADER));
-        attach_shader(program, vs);
+        let vs = try!(compile_shader(vssrc, ShaderStage::Vertex));
+        attach_shader(program.program, vs);
         unsafe {
             gl::DeleteShader(vs);
         }
 
-        let fs = try!(compile_shader(fssrc, gl::FRAGMENT_SHADER));
-        attach_shader(program, fs);
+        let fs = try!(compile_shader(fssrc, ShaderStage::Fragment));
+        attach_shader(program.program, fs);
         unsafe {
             gl::DeleteShader(fs);
         }
 
         match gssrc {
             Some(s) => {
-                let gs = try!(compile_shader(s, gl::GEOMETRY_SHADER));
-                attach_shader(program, gs);
+                let gs = try!(compile_shader(s, ShaderStage::Geometry));
+                attach_shader(program.program, gs);
                 unsafe { gl::DeleteShader(gs) }
             }
             None => (),
         }
 
+        let out = try!(c_string(out));
         unsafe {
             // gl::UseProgram(program);
-            gl::BindFragDataLocation(program, 0, CString::new(out).unwrap().as_ptr());
+            gl::BindFragDataLocation(program.program, 0, out.as_ptr());
         }
 
-        try!(link_program(program));
+        try!(link_program(program.program));
 
-        Ok(GLProgram { program: program })
+        unsafe {
+            program.attributes = get_active_inputs(program.program,
+                                                   gl::ACTIVE_ATTRIBUTES,
+                                                   gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+                                                   gl::GetActiveAttrib,
+                                                   gl::GetAttribLocation);
+            program.uniforms = get_active_inputs(program.program,
+                                                 gl::ACTIVE_UNIFORMS,
+                                                 gl::ACTIVE_UNIFORM_MAX_LENGTH,
+                                                 gl::GetActiveUniform,
+                                                 gl::GetUniformLocation);
+        }
+        {
+            let mut locations = program.locations.borrow_mut();
+            for u in program.uniforms.iter() {
+                if u.name.ends_with("[0]") {
+                    locations.insert(String::from(&u.name[..u.name.len() - 3]), u.location);
+                }
+                locations.insert(u.name.clone(), u.location);
+            }
+        }
+
+        Ok(program)
+    }
+
+    /// Attaches the bound uniform buffers to binding points `0..n` for the
+    /// next draw, so that any number of buffers can exist and programs can
+    /// share them. `bind_uniform_block` keeps `n` within the binding points.
+    fn bind_uniform_blocks(&self) {
+        let alive = self.context.uniform_buffers.borrow();
+        let bindings = self.block_bindings.borrow();
+        for (binding, &(index, buffer, generation)) in bindings.iter().enumerate() {
+            if alive.get(&buffer) != Some(&generation) {
+                continue;
+            }
+            unsafe {
+                gl::UniformBlockBinding(self.program, index, binding as u32);
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, binding as u32, buffer);
+            }
+        }
+    }
+
+    /// Sets `name`, or each element `name[i]` of an array.
+    fn set_uniform(&self,
+                   name: &str,
+                   val: &Uniform<u32>,
+                   texid: &mut u32)
+                   -> Result<(), BackendError> {
+        match val {
+            &Uniform::Array(ref elems) => {
+                for (i, elem) in elems.iter().enumerate() {
+                    try!(self.set_uniform(&format!("{}[{}]", name, i), elem, texid));
+                }
+            }
+            _ => {
+                let loc = try!(self.uniform_location(name));
+                if loc >= 0 {
+                    set_uniform_value(loc, val, texid);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn uniform_location(&self, name: &str) -> Result<i32, BackendError> {
+        if let Some(&loc) = self.locations.borrow().get(name) {
+            return Ok(loc);
+        }
+        let c_name = try!(c_string(name));
+        let loc = unsafe { gl::GetUniformLocation(self.program, c_name.as_ptr()) };
+        self.locations.borrow_mut().insert(String::from(name), loc);
+        Ok(loc)
     }
 }
 
 impl Program for GLProgram {
-    fn draw(&self, draw_type: DrawType, vb: &BVertexBuffer, uniforms: &Uniforms<u32>) {
+    fn multi_draw_instanced(&self,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        try!(check_uniforms(self, uniforms, &self.uniform_warnings));
+        try!(check_ranges(&**vb, ranges));
+        try!(check_indices(&**vb));
+        try!(check_instances(&**vb, instances));
+        let vao = try!(vb.vertex_array(self));
         unsafe {
             gl::UseProgram(self.program);
-            gl::BindVertexArray(vb.get_bind());
-            gl::DrawArrays(draw_type_to_gl_type(draw_type), 0, vb.len() as i32);
         }
-        let names = &uniforms.names;
-        let params = &uniforms.uniforms;
+        self.bind_uniform_blocks();
         let mut texid = 0;
-        for (name, param) in names.iter().zip(params.iter()) {
-            let loc: i32 = 0;
-            unsafe {
-                gl::GetUniformLocation(self.program,
-                                       CString::new(name.clone().into_bytes()).unwrap().as_ptr());
+        for (name, value) in uniforms.names.iter().zip(uniforms.uniforms.iter()) {
+            try!(self.set_uniform(name, value, &mut texid));
+        }
+        self.context.apply_parameters(params);
+        let mode = draw_type_to_gl_type(draw_type);
+        unsafe {
+            gl::BindVertexArray(vao);
+            let counts: Vec<i32> = ranges.iter().map(|r| (r.end - r.start) as i32).collect();
+            match vb.get_indices() {
+                Some(indices) => {
+                    if vb.primitive_restart() {
+                        gl::Enable(gl::PRIMITIVE_RESTART);
+                        gl::PrimitiveRestartIndex(indices.restart_index());
+                    }
+                    let ty = index_type_to_gl_type(indices);
+                    let offsets: Vec<*const GLvoid> = ranges.iter()
+                        .map(|r| (r.start * indices.size()) as *const GLvoid)
+                        .collect();
+                    if instances == 1 {
+                        gl::MultiDrawElements(mode,
+                                              counts.as_ptr(),
+                                              ty,
+                                              offsets.as_ptr(),
+                                              ranges.len() as i32);
+                    } else {
+                        for (&count, &offset) in counts.iter().zip(offsets.iter()) {
+                            gl::DrawElementsInstanced(mode, count, ty, offset, instances as i32);
+                        }
+                    }
+                    if vb.primitive_restart() {
+                        gl::Disable(gl::PRIMITIVE_RESTART);
+                    }
+                }
+                None => {
+                    let firsts: Vec<i32> = ranges.iter().map(|r| r.start as i32).collect();
+                    if instances == 1 {
+                        gl::MultiDrawArrays(mode,
+                                            firsts.as_ptr(),
+                                            counts.as_ptr(),
+                                            ranges.len() as i32);
+                    } else {
+                        for (&first, &count) in firsts.iter().zip(counts.iter()) {
+                            gl::DrawArraysInstanced(mode, first, count, instances as i32);
+                        }
+                    }
+                }
             }
-            set_uniform_value(loc, param, &mut texid);
         }
+        Ok(())
     }
     fn get_bind(&self) -> u32 {
         self.program
     }
+    fn attributes(&self) -> &Vec<ProgramInput> {
+        &self.attributes
+    }
+    fn uniforms(&self) -> &Vec<ProgramInput> {
+        &self.uniforms
+    }
+    fn unused_uniforms(&self) -> Vec<String> {
+        self.uniform_warnings.names()
+    }
+    fn bind_uniform_block(&self, name: &str, buffer: &BUniformBuffer) -> Result<(), BackendError> {
+        let c_name = try!(c_string(name));
+        unsafe {
+            let index = gl::GetUniformBlockIndex(self.program, c_name.as_ptr());
+            if index == gl::INVALID_INDEX {
+                return Err(BackendError::InvalidUniformBlock { name: String::from(name) });
+            }
+            let mut size: GLint = 0;
+            gl::GetActiveUniformBlockiv(self.program,
+                                        index,
+                                        gl::UNIFORM_BLOCK_DATA_SIZE,
+                                        &mut size);
+            if buffer.size() < size as usize {
+                return Err(BackendError::InvalidBufferSize {
+                    expected: size as usize,
+                    actual: buffer.size(),
+                });
+            }
+            let mut bindings = self.block_bindings.borrow_mut();
+            bindings.retain(|&(i, _, _)| i != index);
+            // each block bound takes one binding point at draw time
+            let mut max: GLint = 0;
+            gl::GetIntegerv(gl::MAX_UNIFORM_BUFFER_BINDINGS, &mut max);
+            if bindings.len() >= max as usize {
+                return Err(BackendError::Unsupported(format!("more than {} uniform blocks in \
+                                                              one program",
+                                                             max)));
+            }
+            let bind = buffer.get_bind();
+            let generation = self.context.uniform_buffers.borrow().get(&bind).cloned();
+            bindings.push((index, bind, generation.unwrap_or(0)));
+        }
+        Ok(())
+    }
+}
+
+type GetActiveFn = unsafe fn(GLuint,
+                             GLuint,
+                             GLsizei,
+                             *mut GLsizei,
+                             *mut GLint,
+                             *mut GLenum,
+                             *mut GLchar);
+type GetLocationFn = unsafe fn(GLuint, *const GLchar) -> GLint;
+
+/// Queries the active attributes or uniforms of a linked program.
+unsafe fn get_active_inputs(program: u32,
+                            count: GLenum,
+                            max_length: GLenum,
+                            get_active: GetActiveFn,
+                            get_location: GetLocationFn)
+                            -> Vec<ProgramInput> {
+    let mut n: GLint = 0;
+    let mut max_len: GLint = 0;
+    gl::GetProgramiv(program, count, &mut n);
+    gl::GetProgramiv(program, max_length, &mut max_len);
+    (0..n as u32)
+        .map(|i| {
+            let mut buf: Vec<u8> = vec![0; max_len as usize + 1];
+            let mut len: GLsizei = 0;
+            let mut size: GLint = 0;
+            let mut ty: GLenum = 0;
+            get_active(program,
+                       i,
+                       buf.len() as GLsizei,
+                       &mut len,
+                       &mut size,
+                       &mut ty,
+                       buf.as_mut_ptr() as *mut GLchar);
+            buf.truncate(len as usize);
+            let location = get_location(program, buf.as_ptr() as *const GLchar);
+            ProgramInput {
+                name: String::from_utf8_lossy(&buf).into_owned(),
+                ty: gl_type_to_data_type(ty),
+                size: size as usize,
+                location: location,
+            }
+        })
+        .collect()
+}
+
+pub fn gl_type_to_data_type(t: GLenum) -> DataType {
+    use DataType::*;
+    match t {
+        gl::FLOAT => Float,
+        gl::FLOAT_VEC2 => Vec2,
+        gl::FLOAT_VEC3 => Vec3,
+        gl::FLOAT_VEC4 => Vec4,
+        gl::INT => Int,
+        gl::INT_VEC2 => IVec2,
+        gl::INT_VEC3 => IVec3,
+        gl::INT_VEC4 => IVec4,
+        gl::UNSIGNED_INT => UInt,
+        gl::UNSIGNED_INT_VEC2 => UVec2,
+        gl::UNSIGNED_INT_VEC3 => UVec3,
+        gl::UNSIGNED_INT_VEC4 => UVec4,
+        gl::BOOL => Bool,
+        gl::BOOL_VEC2 => BVec2,
+        gl::BOOL_VEC3 => BVec3,
+        gl::BOOL_VEC4 => BVec4,
+        gl::FLOAT_MAT2 => Mat2,
+        gl::FLOAT_MAT3 => Mat3,
+        gl::FLOAT_MAT4 => Mat4,
+        gl::FLOAT_MAT2x3 => Mat2x3,
+        gl::FLOAT_MAT2x4 => Mat2x4,
+        gl::FLOAT_MAT3x2 => Mat3x2,
+        gl::FLOAT_MAT3x4 => Mat3x4,
+        gl::FLOAT_MAT4x2 => Mat4x2,
+        gl::FLOAT_MAT4x3 => Mat4x3,
+        gl::SAMPLER_2D => Sampler2D,
+        _ => Other,
+    }
 }
 
 pub fn draw_type_to_gl_type(t: DrawType) -> GLenum {
     use DrawType::*;
     match t {
+        Points => gl::POINTS,
+        Lines => gl::LINES,
+        LineStrip => gl::LINE_STRIP,
+        LineLoop => gl::LINE_LOOP,
         Triangles => gl::TRIANGLES,
         TriangleStrip => gl::TRIANGLE_STRIP,
-        // _ => panic!("{:?}: this type is still not supported draw type.", t),
+        TriangleFan => gl::TRIANGLE_FAN,
+        LinesAdjacency => gl::LINES_ADJACENCY,
+        LineStripAdjacency => gl::LINE_STRIP_ADJACENCY,
+        TrianglesAdjacency => gl::TRIANGLES_ADJACENCY,
+        TriangleStripAdjacency => gl::TRIANGLE_STRIP_ADJACENCY,
     }
 }
 
-pub fn set_uniform_value(loc: i32, val: &Uniform<u32>, texid: &mut u32) {
+/// Sets one value; arrays are split into their elements by
+/// `GLProgram::set_uniform` first.
+fn set_uniform_value(loc: i32, val: &Uniform<u32>, texid: &mut u32) {
     use Uniform::*;
-    match val {
-        &Vec2(x, y) => unsafe {
-            gl::Uniform2f(loc, x, y);
-        },
-        &Vec3(x, y, z) => unsafe {
-            gl::Uniform3f(loc, x, y, z);
-        },
-        &Matrix(m) => unsafe {
-            gl::UniformMatrix4fv(loc, 1, gl::TRUE, mem::transmute(&m[0]));
-        },
-        &Texture2D(b) => unsafe {
-            gl::ActiveTexture(gl::TEXTURE0 + *texid); // u32 to i32 ?
-            gl::BindTexture(gl::TEXTURE_2D, b);
-            gl::Uniform1i(loc, *texid as i32);
-            *texid += 1;
-        },
-        // _ => {
-        //     panic!("{:?}: this type is still not supported parameter type.",
-        //            val);
-        // }
+    unsafe {
+        match val {
+            &Float(x) => gl::Uniform1f(loc, x),
+            &Int(x) => gl::Uniform1i(loc, x),
+            &UInt(x) => gl::Uniform1ui(loc, x),
+            &Bool(x) => gl::Uniform1i(loc, x as i32),
+            &Vec2(x, y) => gl::Uniform2f(loc, x, y),
+            &Vec3(x, y, z) => gl::Uniform3f(loc, x, y, z),
+            &Vec4(x, y, z, w) => gl::Uniform4f(loc, x, y, z, w),
+            &IVec2(x, y) => gl::Uniform2i(loc, x, y),
+            &IVec3(x, y, z) => gl::Uniform3i(loc, x, y, z),
+            &IVec4(x, y, z, w) => gl::Uniform4i(loc, x, y, z, w),
+            &UVec2(x, y) => gl::Uniform2ui(loc, x, y),
+            &UVec3(x, y, z) => gl::Uniform3ui(loc, x, y, z),
+            &UVec4(x, y, z, w) => gl::Uniform4ui(loc, x, y, z, w),
+            &BVec2(x, y) => gl::Uniform2i(loc, x as i32, y as i32),
+            &BVec3(x, y, z) => gl::Uniform3i(loc, x as i32, y as i32, z as i32),
+            &BVec4(x, y, z, w) => gl::Uniform4i(loc, x as i32, y as i32, z as i32, w as i32),
+            // matrices are given row by row, hence the transpose
+            &Mat2(ref m) => gl::UniformMatrix2fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat3(ref m) => gl::UniformMatrix3fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Matrix(ref m) => gl::UniformMatrix4fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat2x3(ref m) => gl::UniformMatrix2x3fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat2x4(ref m) => gl::UniformMatrix2x4fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat3x2(ref m) => gl::UniformMatrix3x2fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat3x4(ref m) => gl::UniformMatrix3x4fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat4x2(ref m) => gl::UniformMatrix4x2fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Mat4x3(ref m) => gl::UniformMatrix4x3fv(loc, 1, gl::TRUE, m.as_ptr() as *const _),
+            &Texture2D(b) => {
+                gl::ActiveTexture(gl::TEXTURE0 + *texid);
+                gl::BindTexture(gl::TEXTURE_2D, b);
+                gl::Uniform1i(loc, *texid as i32);
+                *texid += 1;
+            }
+            &Array(..) => unreachable!("arrays are set element by element"),
+        }
     }
 }
 
@@ -267,35 +1203,86 @@ impl Drop for GLProgram {
 }
 
 pub struct GLBuffer {
-    buffer: InputBuffer,
+    data: Vec<u8>,
+    layout: VertexLayout,
+    usage: BufferUsage,
     bind: u32,
 }
 
 impl GLBuffer {
-    pub fn new(buffer: InputBuffer) -> GLBuffer {
+    pub fn new(data: Vec<u8>, layout: VertexLayout, usage: BufferUsage) -> GLBuffer {
         let mut bind: u32 = 0;
         unsafe {
             gl::GenBuffers(1, &mut bind);
             gl::BindBuffer(gl::ARRAY_BUFFER, bind);
             gl::BufferData(gl::ARRAY_BUFFER,
-                           buffer.buffer_size() as isize,
-                           mem::transmute(&buffer.as_slice()[0]),
-                           gl::STATIC_DRAW);
+                           data.len() as isize,
+                           data.as_ptr() as *const _,
+                           buffer_usage_to_gl_type(usage));
         }
         GLBuffer {
-            buffer: buffer,
+            data: data,
+            layout: layout,
+            usage: usage,
             bind: bind,
         }
     }
 }
 
 impl Buffer for GLBuffer {
-    fn get_buffer(&self) -> &InputBuffer {
-        &self.buffer
+    fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+    fn get_layout(&self) -> &VertexLayout {
+        &self.layout
     }
     fn get_bind(&self) -> u32 {
         self.bind
     }
+    fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), BackendError> {
+        try!(check_write(self.data.len(), offset, data.len()));
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.bind);
+            // invalidating the range lets the driver hand out fresh memory
+            // instead of waiting for draws still reading the old one
+            let map = match self.usage {
+                BufferUsage::Stream => {
+                    gl::MapBufferRange(gl::ARRAY_BUFFER,
+                                       offset as isize,
+                                       data.len() as isize,
+                                       gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT)
+                }
+                _ => ptr::null_mut(),
+            };
+            if !map.is_null() {
+                ptr::copy_nonoverlapping(data.as_ptr(), map as *mut u8, data.len());
+                gl::UnmapBuffer(gl::ARRAY_BUFFER);
+            } else {
+                gl::BufferSubData(gl::ARRAY_BUFFER,
+                                  offset as isize,
+                                  data.len() as isize,
+                                  data.as_ptr() as *const _);
+            }
+        }
+        Ok(())
+    }
+    fn set_data(&mut self, data: &[u8]) -> Result<(), BackendError> {
+        try!(self.layout.check(data.len()));
+        self.data = data.to_vec();
+        unsafe {
+            // a new store for the same buffer name, so the VAO keeps working
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.bind);
+            gl::BufferData(gl::ARRAY_BUFFER,
+                           data.len() as isize,
+                           data.as_ptr() as *const _,
+                           buffer_usage_to_gl_type(self.usage));
+        }
+        Ok(())
+    }
 }
 
 impl Drop for GLBuffer {
@@ -307,18 +1294,61 @@ impl Drop for GLBuffer {
 }
 
 pub struct GLVertexBuffer {
-    names: Vec<String>,
     buffers: Vec<BBuffer>,
-    vao: u32,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
+    max_index: Option<u32>,
+    /// One VAO per distinct set of attribute locations the buffer's inputs
+    /// were given by the programs drawing it, -1 for inputs left out.
+    vaos: RefCell<HashMap<Vec<i32>, u32>>,
+    /// The element array buffer, 0 when not indexed.
+    index_bind: u32,
 }
 
 impl GLVertexBuffer {
-    pub fn new(names: Vec<String>, buffers: Vec<BBuffer>, vao: u32) -> GLVertexBuffer {
-        GLVertexBuffer {
-            names: names,
-            buffers: buffers,
-            vao: vao,
+    fn create_vertex_array(&self, locations: &[i32]) -> u32 {
+        let mut vao: u32 = 0;
+        let mut locations = locations.iter();
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+            for buffer in self.buffers.iter() {
+                let layout = buffer.get_layout();
+                gl::BindBuffer(gl::ARRAY_BUFFER, buffer.get_bind());
+                for (attribute, &loc) in layout.attributes.iter().zip(&mut locations) {
+                    if loc < 0 {
+                        continue;
+                    }
+                    let loc = loc as u32;
+                    let ty = attribute_type_to_gl_type(attribute.ty);
+                    if attribute.ty.is_integer() {
+                        gl::VertexAttribIPointer(loc,
+                                                 attribute.components as i32,
+                                                 ty,
+                                                 layout.stride as i32,
+                                                 attribute.offset as *const _);
+                    } else {
+                        let normalized = match attribute.ty {
+                            AttributeType::U8Norm | AttributeType::I16Norm => gl::TRUE,
+                            _ => gl::FALSE,
+                        };
+                        gl::VertexAttribPointer(loc,
+                                                attribute.components as i32,
+                                                ty,
+                                                normalized,
+                                                layout.stride as i32,
+                                                attribute.offset as *const _);
+                    }
+                    gl::EnableVertexAttribArray(loc);
+                    gl::VertexAttribDivisor(loc, layout.divisor);
+                }
+            }
+            // the element array binding is part of the VAO
+            if self.index_bind != 0 {
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_bind);
+            }
         }
+        vao
     }
 }
 
@@ -326,56 +1356,141 @@ impl VertexBuffer for GLVertexBuffer {
     fn get_buffers(&self) -> &Vec<BBuffer> {
         &self.buffers
     }
-    fn get_names(&self) -> &Vec<String> {
-        &self.names
+    fn buffer_mut(&mut self, i: usize) -> &mut Buffer {
+        &mut *self.buffers[i]
     }
-    fn get_bind(&self) -> u32 {
-        self.vao
+    fn vertex_array(&self, program: &Program) -> Result<u32, BackendError> {
+        try!(check_inputs(program, &self.buffers));
+        // built-ins like gl_VertexID are active but have no location
+        let locations: Vec<i32> = self.buffers
+            .iter()
+            .flat_map(|b| b.get_layout().attributes.iter())
+            .map(|a| match find_input(program.attributes(), &a.name) {
+                Some(input) => input.location,
+                None => -1,
+            })
+            .collect();
+        if let Some(&vao) = self.vaos.borrow().get(&locations) {
+            return Ok(vao);
+        }
+        let vao = self.create_vertex_array(&locations);
+        self.vaos.borrow_mut().insert(locations, vao);
+        Ok(vao)
+    }
+    fn get_indices(&self) -> Option<&IndexBuffer> {
+        self.indices.as_ref()
+    }
+    fn primitive_restart(&self) -> bool {
+        self.primitive_restart
+    }
+    fn max_index(&self) -> Option<u32> {
+        self.max_index
+    }
+}
+
+impl Drop for GLVertexBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            for vao in self.vaos.borrow().values() {
+                gl::DeleteVertexArrays(1, vao);
+            }
+            if self.index_bind != 0 {
+                gl::DeleteBuffers(1, &self.index_bind);
+            }
+        }
     }
 }
 
 pub struct GLVertexBufferBuilder {
-    names: Vec<String>,
     buffers: Vec<BBuffer>,
+    usage: BufferUsage,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
 }
 
 impl GLVertexBufferBuilder {
-    pub fn new() -> GLVertexBufferBuilder {
+    pub fn new(_context: &Rc<GLContext>) -> GLVertexBufferBuilder {
         GLVertexBufferBuilder {
-            names: Vec::new(),
             buffers: Vec::new(),
+            usage: BufferUsage::Static,
+            indices: None,
+            primitive_restart: false,
         }
     }
 }
 impl VertexBufferBuilder for GLVertexBufferBuilder {
-    fn add_input(mut self, name: &str, input: InputBuffer) -> GLVertexBufferBuilder {
-        self.names.push(String::from(name));
-        self.buffers.push(Box::new(GLBuffer::new(input)));
+    fn add_buffer<T: Copy>(mut self, layout: VertexLayout, data: &[T]) -> GLVertexBufferBuilder {
+        self.buffers.push(Box::new(GLBuffer::new(slice_as_bytes(data).to_vec(),
+                                                 layout,
+                                                 self.usage)));
         self
     }
-    fn build(self, program: &BProgram) -> BVertexBuffer {
-        let mut vao: u32 = 0;
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
-            for (name, buffer) in self.names.iter().zip(self.buffers.iter()) {
-                gl::BindBuffer(gl::ARRAY_BUFFER, buffer.get_bind());
-                let loc: u32 =
-                    gl::GetAttribLocation(program.get_bind(),
-                                          CString::new((&name).as_bytes())
-                                              .unwrap()
-                                              .as_ptr()) as u32;
-
-                gl::VertexAttribPointer(loc,
-                                        buffer.elem_len() as i32,
-                                        gl::FLOAT,
-                                        gl::FALSE,
-                                        0,
-                                        ptr::null());
-                gl::EnableVertexAttribArray(loc);
+    fn usage(mut self, usage: BufferUsage) -> GLVertexBufferBuilder {
+        self.usage = usage;
+        self
+    }
+    fn indices(mut self, indices: IndexBuffer) -> GLVertexBufferBuilder {
+        self.indices = Some(indices);
+        self
+    }
+    fn primitive_restart(mut self, enabled: bool) -> GLVertexBufferBuilder {
+        self.primitive_restart = enabled;
+        self
+    }
+    fn build(self) -> Result<BVertexBuffer, BackendError> {
+        let max_index = try!(check_buffers(&self.buffers,
+                                           self.indices.as_ref(),
+                                           self.primitive_restart));
+        let mut index_bind: u32 = 0;
+        if let Some(ref indices) = self.indices {
+            unsafe {
+                // uploaded through ARRAY_BUFFER so no bound VAO picks it up
+                gl::GenBuffers(1, &mut index_bind);
+                gl::BindBuffer(gl::ARRAY_BUFFER, index_bind);
+                gl::BufferData(gl::ARRAY_BUFFER,
+                               indices.as_bytes().len() as isize,
+                               indices.as_bytes().as_ptr() as *const _,
+                               gl::STATIC_DRAW);
             }
         }
-        Box::new(GLVertexBuffer::new(self.names, self.buffers, vao))
+        Ok(Box::new(GLVertexBuffer {
+            buffers: self.buffers,
+            indices: self.indices,
+            primitive_restart: self.primitive_restart,
+            max_index: max_index,
+            vaos: RefCell::new(HashMap::new()),
+            index_bind: index_bind,
+        }))
+    }
+}
+
+pub fn index_type_to_gl_type(indices: &IndexBuffer) -> GLenum {
+    use IndexBuffer::*;
+    match indices {
+        &U8(..) => gl::UNSIGNED_BYTE,
+        &U16(..) => gl::UNSIGNED_SHORT,
+        &U32(..) => gl::UNSIGNED_INT,
+    }
+}
+
+pub fn buffer_usage_to_gl_type(usage: BufferUsage) -> GLenum {
+    use BufferUsage::*;
+    match usage {
+        Static => gl::STATIC_DRAW,
+        Dynamic => gl::DYNAMIC_DRAW,
+        Stream => gl::STREAM_DRAW,
+    }
+}
+
+pub fn attribute_type_to_gl_type(t: AttributeType) -> GLenum {
+    use AttributeType::*;
+    match t {
+        F32 => gl::FLOAT,
+        F16 => gl::HALF_FLOAT,
+        I32 => gl::INT,
+        U32 => gl::UNSIGNED_INT,
+        U8Norm => gl::UNSIGNED_BYTE,
+        I16Norm => gl::SHORT,
     }
 }
 
@@ -396,11 +1511,18 @@ pub fn color_format_to_gl_type(format: ColorFormat) -> GLenum {
 }
 
 impl GLTexture2D {
-    pub fn new(format: ColorFormat, width: u32, height: u32, data: Vec<u8>) -> GLTexture2D {
+    pub fn new(_context: &Rc<GLContext>,
+               format: ColorFormat,
+               width: u32,
+               height: u32,
+               data: Vec<u8>)
+               -> Result<GLTexture2D, BackendError> {
+        try!(format.check_data(width, height, &data));
         let mut bind: u32 = 0;
         unsafe {
             gl::GenTextures(1, &mut bind);
             gl::BindTexture(gl::TEXTURE_2D, bind);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
             gl::TexImage2D(gl::TEXTURE_2D,
                            0,
                            color_format_to_gl_type(format) as i32,
@@ -409,19 +1531,19 @@ impl GLTexture2D {
                            0,
                            color_format_to_gl_type(format),
                            gl::UNSIGNED_BYTE,
-                           mem::transmute(&data.as_slice()[0]));
+                           data.as_ptr() as *const _);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
         }
-        GLTexture2D {
+        Ok(GLTexture2D {
             format: format,
             width: width,
             height: height,
             buffer: data,
             bind: bind,
-        }
+        })
     }
 }
 
@@ -429,4 +1551,163 @@ impl Texture2D for GLTexture2D {
     fn get_bind(&self) -> u32 {
         self.bind
     }
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+pub struct GLFramebuffer {
+    context: Rc<GLContext>,
+    bind: u32,
+    /// The depth/stencil renderbuffer, 0 without one.
+    renderbuffer: u32,
+    size: (u32, u32),
+}
+
+impl GLFramebuffer {
+    pub fn new(context: &Rc<GLContext>,
+               colors: &[&BTexture2D],
+               depth: DepthStencil)
+               -> Result<GLFramebuffer, BackendError> {
+        let (width, height) = try!(check_attachments(colors));
+        let mut max: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_DRAW_BUFFERS, &mut max);
+        }
+        if colors.len() > max as usize {
+            return Err(BackendError::Unsupported(format!("more than {} color attachments", max)));
+        }
+        let mut bind: u32 = 0;
+        let mut renderbuffer: u32 = 0;
+        let status = unsafe {
+            gl::GenFramebuffers(1, &mut bind);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, bind);
+            let attachments: Vec<GLenum> = (0..colors.len() as u32)
+                .map(|i| gl::COLOR_ATTACHMENT0 + i)
+                .collect();
+            for (texture, &attachment) in colors.iter().zip(attachments.iter()) {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER,
+                                         attachment,
+                                         gl::TEXTURE_2D,
+                                         texture.get_bind(),
+                                         0);
+            }
+            gl::DrawBuffers(attachments.len() as i32, attachments.as_ptr());
+            let storage = match depth {
+                DepthStencil::None => None,
+                DepthStencil::Depth => Some((gl::DEPTH_COMPONENT24, gl::DEPTH_ATTACHMENT)),
+                DepthStencil::DepthStencil => {
+                    Some((gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL_ATTACHMENT))
+                }
+            };
+            if let Some((format, attachment)) = storage {
+                gl::GenRenderbuffers(1, &mut renderbuffer);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+                gl::RenderbufferStorage(gl::RENDERBUFFER, format, width as i32, height as i32);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER,
+                                            attachment,
+                                            gl::RENDERBUFFER,
+                                            renderbuffer);
+            }
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, context.framebuffer);
+            status
+        };
+        let framebuffer = GLFramebuffer {
+            context: context.clone(),
+            bind: bind,
+            renderbuffer: renderbuffer,
+            size: (width, height),
+        };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(BackendError::InvalidFramebuffer(format!("incomplete (status {:#x})",
+                                                                status)));
+        }
+        Ok(framebuffer)
+    }
+}
+
+impl Framebuffer for GLFramebuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+    fn frame(&self) -> BFrame {
+        Box::new(GLFrame::with_target(self.context.clone(), Some(self.bind), self.size))
+    }
+}
+
+impl Drop for GLFramebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.bind);
+            if self.renderbuffer != 0 {
+                gl::DeleteRenderbuffers(1, &self.renderbuffer);
+            }
+        }
+    }
+}
+
+pub struct GLUniformBuffer {
+    context: Rc<GLContext>,
+    bind: u32,
+    size: usize,
+}
+
+impl GLUniformBuffer {
+    pub fn new(context: &Rc<GLContext>, data: &[u8]) -> Result<GLUniformBuffer, BackendError> {
+        let mut bind: u32 = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut bind);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, bind);
+            gl::BufferData(gl::UNIFORM_BUFFER,
+                           data.len() as isize,
+                           data.as_ptr() as *const _,
+                           gl::DYNAMIC_DRAW);
+        }
+        let generation = context.uniform_buffer_generation.get() + 1;
+        context.uniform_buffer_generation.set(generation);
+        context.uniform_buffers.borrow_mut().insert(bind, generation);
+        Ok(GLUniformBuffer {
+            context: context.clone(),
+            bind: bind,
+            size: data.len(),
+        })
+    }
+}
+
+impl UniformBuffer for GLUniformBuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn size(&self) -> usize {
+        self.size
+    }
+    fn write(&self, data: &[u8]) -> Result<(), BackendError> {
+        if data.len() != self.size {
+            return Err(BackendError::InvalidBufferSize {
+                expected: self.size,
+                actual: data.len(),
+            });
+        }
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.bind);
+            gl::BufferSubData(gl::UNIFORM_BUFFER,
+                              0,
+                              data.len() as isize,
+                              data.as_ptr() as *const _);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GLUniformBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.bind);
+        }
+        self.context.uniform_buffers.borrow_mut().remove(&self.bind);
+    }
 }
@@ -0,0 +1,280 @@
+/// Fixed-function state of one draw call. The default is GL's initial
+/// state: no depth, stencil or scissor test, no blending, no culling, all
+/// channels written, polygons filled and the whole frame as viewport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawParameters {
+    pub depth: Depth,
+    /// `None` writes the fragment color as is.
+    pub blend: Option<Blend>,
+    pub cull: Cull,
+    /// The winding of front-facing polygons, as seen on screen.
+    pub front_face: Winding,
+    /// `None` disables the stencil test.
+    pub stencil: Option<Stencil>,
+    /// Whether red, green, blue and alpha are written.
+    pub color_mask: [bool; 4],
+    /// Depth offset of polygons, e.g. against shadow acne and z-fighting
+    /// of decals.
+    pub polygon_offset: Option<PolygonOffset>,
+    pub polygon_mode: PolygonMode,
+    /// The part of the frame clip space maps to; `None` is the whole frame.
+    pub viewport: Option<Rect>,
+    /// Discards fragments outside of it; `None` disables the scissor test.
+    pub scissor: Option<Rect>,
+}
+
+impl Default for DrawParameters {
+    fn default() -> DrawParameters {
+        DrawParameters {
+            depth: Depth::default(),
+            blend: None,
+            cull: Cull::None,
+            front_face: Winding::CounterClockwise,
+            stencil: None,
+            color_mask: [true; 4],
+            polygon_offset: None,
+            polygon_mode: PolygonMode::Fill,
+            viewport: None,
+            scissor: None,
+        }
+    }
+}
+
+/// A rectangle in pixels, measured from the bottom left corner of the frame
+/// as in GL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub left: u32,
+    pub bottom: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The whole of a frame of the given size.
+    pub fn full((width, height): (u32, u32)) -> Rect {
+        Rect {
+            left: 0,
+            bottom: 0,
+            width: width,
+            height: height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl Comparison {
+    /// Whether `incoming` passes against `stored`.
+    pub fn compare<T: PartialOrd>(self, incoming: T, stored: T) -> bool {
+        use self::Comparison::*;
+        match self {
+            Never => false,
+            Less => incoming < stored,
+            Equal => incoming == stored,
+            LessOrEqual => incoming <= stored,
+            Greater => incoming > stored,
+            NotEqual => incoming != stored,
+            GreaterOrEqual => incoming >= stored,
+            Always => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Depth {
+    pub test: Comparison,
+    pub write: bool,
+}
+
+impl Depth {
+    /// Keeps the nearest fragment, the usual setting for opaque geometry.
+    pub fn less() -> Depth {
+        Depth {
+            test: Comparison::Less,
+            write: true,
+        }
+    }
+    /// Whether the depth buffer is used at all.
+    pub fn is_enabled(&self) -> bool {
+        self.test != Comparison::Always || self.write
+    }
+}
+
+impl Default for Depth {
+    fn default() -> Depth {
+        Depth {
+            test: Comparison::Always,
+            write: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Blend {
+    pub color: BlendEquation,
+    pub alpha: BlendEquation,
+    /// The color `BlendFactor::Constant*` refer to.
+    pub constant: [f32; 4],
+}
+
+impl Blend {
+    /// Mixes by the source alpha, for non-premultiplied transparency.
+    pub fn alpha() -> Blend {
+        let equation = BlendEquation {
+            op: BlendOp::Add,
+            source: BlendFactor::SourceAlpha,
+            destination: BlendFactor::OneMinusSourceAlpha,
+        };
+        Blend {
+            color: equation,
+            alpha: equation,
+            constant: [0.0; 4],
+        }
+    }
+    /// Adds the source to what is drawn already.
+    pub fn additive() -> Blend {
+        let equation = BlendEquation {
+            op: BlendOp::Add,
+            source: BlendFactor::One,
+            destination: BlendFactor::One,
+        };
+        Blend {
+            color: equation,
+            alpha: equation,
+            constant: [0.0; 4],
+        }
+    }
+}
+
+/// `op(source * source factor, destination * destination factor)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlendEquation {
+    pub op: BlendOp,
+    pub source: BlendFactor,
+    pub destination: BlendFactor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendOp {
+    Add,
+    /// Source minus destination.
+    Subtract,
+    /// Destination minus source.
+    ReverseSubtract,
+    /// The smaller of source and destination, ignoring the factors.
+    Min,
+    /// The larger of source and destination, ignoring the factors.
+    Max,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SourceColor,
+    OneMinusSourceColor,
+    DestinationColor,
+    OneMinusDestinationColor,
+    SourceAlpha,
+    OneMinusSourceAlpha,
+    DestinationAlpha,
+    OneMinusDestinationAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    ConstantAlpha,
+    OneMinusConstantAlpha,
+    SourceAlphaSaturate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cull {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// The stencil test and what it does to the stencil buffer, for both
+/// faces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stencil {
+    /// Compares `reference & read_mask` against the stored value masked
+    /// the same way.
+    pub test: Comparison,
+    pub reference: u8,
+    pub read_mask: u8,
+    /// Bits of the stored value the operations may change.
+    pub write_mask: u8,
+    /// Applied when the stencil test fails.
+    pub fail: StencilOp,
+    /// Applied when the stencil test passes but the depth test fails.
+    pub depth_fail: StencilOp,
+    /// Applied when both tests pass.
+    pub pass: StencilOp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    /// Stores the reference value.
+    Replace,
+    /// Increments, saturating at 255.
+    Increment,
+    IncrementWrap,
+    /// Decrements, saturating at 0.
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    pub fn apply(self, stored: u8, reference: u8) -> u8 {
+        use self::StencilOp::*;
+        match self {
+            Keep => stored,
+            Zero => 0,
+            Replace => reference,
+            Increment => stored.saturating_add(1),
+            IncrementWrap => stored.wrapping_add(1),
+            Decrement => stored.saturating_sub(1),
+            DecrementWrap => stored.wrapping_sub(1),
+            Invert => !stored,
+        }
+    }
+}
+
+/// Offsets depth by `factor` times the polygon's depth slope plus `units`
+/// times the smallest resolvable depth difference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolygonOffset {
+    pub factor: f32,
+    pub units: f32,
+}
+
+/// How polygons are rasterized; lines and points are unaffected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolygonMode {
+    Fill,
+    /// Only the edges, `Frame::line_width` wide.
+    Line,
+    /// Only the vertices, `Frame::point_size` large.
+    Point,
+}
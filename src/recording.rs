@@ -0,0 +1,579 @@
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
+
+use image::RgbaImage;
+
+use super::backend::*;
+use super::error::*;
+use super::event::*;
+use super::parameters::*;
+use super::glsl;
+
+/// A call submitted to a `Recorder`. Resources are referred to by the binds
+/// their handles report through `get_bind`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    CreateProgram {
+        program: u32,
+        vssrc: String,
+        fssrc: String,
+        gssrc: Option<String>,
+        out: String,
+    },
+    /// `buffers` holds the bind and usage of each buffer, `inputs` each
+    /// input's name, length in elements and divisor, elements being vertices
+    /// for a divisor of 0 and instances otherwise.
+    CreateVertexBuffer {
+        vertex_buffer: u32,
+        buffers: Vec<(u32, BufferUsage)>,
+        inputs: Vec<(String, usize, u32)>,
+        indices: Option<IndexBuffer>,
+        primitive_restart: bool,
+    },
+    WriteBuffer {
+        buffer: u32,
+        offset: usize,
+        data: Vec<u8>,
+    },
+    SetBufferData {
+        buffer: u32,
+        data: Vec<u8>,
+    },
+    CreateTexture2D {
+        texture: u32,
+        format: ColorFormat,
+        width: u32,
+        height: u32,
+    },
+    CreateUniformBuffer {
+        buffer: u32,
+        data: Vec<u8>,
+    },
+    WriteUniformBuffer {
+        buffer: u32,
+        data: Vec<u8>,
+    },
+    BindUniformBlock {
+        program: u32,
+        block: String,
+        buffer: u32,
+    },
+    /// `colors` holds the textures attached, in the order of the fragment
+    /// outputs.
+    CreateFramebuffer {
+        framebuffer: u32,
+        colors: Vec<u32>,
+        depth: DepthStencil,
+    },
+    /// Draws and clears go to `framebuffer` from now on, 0 being the
+    /// window's.
+    BindFramebuffer(u32),
+    Clear {
+        color: Option<[f32; 4]>,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    },
+    LineWidth(f32),
+    PointSize(f32),
+    Draw {
+        program: u32,
+        draw_type: DrawType,
+        vertex_buffer: u32,
+        ranges: Vec<Range<usize>>,
+        instances: usize,
+        uniforms: Uniforms<u32>,
+        params: DrawParameters,
+    },
+    Finish,
+}
+
+/// A `Facade` that rasterizes nothing and only records what was submitted,
+/// for unit testing code built on top of the backend.
+pub struct Recorder {
+    context: Rc<RecContext>,
+}
+
+impl Recorder {
+    pub fn new(width: u32, height: u32) -> Recorder {
+        Recorder { context: Rc::new(RecContext::new(width, height)) }
+    }
+    pub fn commands(&self) -> Vec<Command> {
+        self.context.commands.borrow().clone()
+    }
+    /// Returns the commands recorded so far and starts a new recording.
+    pub fn take_commands(&self) -> Vec<Command> {
+        self.context.commands.borrow_mut().drain(..).collect()
+    }
+}
+
+impl_facade!(Recorder, context, {
+    Context => RecContext,
+    Frame => RecFrame,
+    Program => RecProgram,
+    VertexBufferBuilder => RecVertexBufferBuilder,
+    Texture2D => RecTexture2D,
+    UniformBuffer => RecUniformBuffer,
+    Framebuffer => RecFramebuffer,
+});
+
+pub struct RecContext {
+    width: u32,
+    height: u32,
+    commands: RefCell<Vec<Command>>,
+    next_bind: Cell<u32>,
+    framebuffer: Cell<u32>,
+}
+
+impl RecContext {
+    pub fn new(width: u32, height: u32) -> RecContext {
+        RecContext {
+            width: width,
+            height: height,
+            commands: RefCell::new(Vec::new()),
+            next_bind: Cell::new(1),
+            framebuffer: Cell::new(0),
+        }
+    }
+    fn record(&self, command: Command) {
+        self.commands.borrow_mut().push(command);
+    }
+    fn gen_bind(&self) -> u32 {
+        let bind = self.next_bind.get();
+        self.next_bind.set(bind + 1);
+        bind
+    }
+    fn bind_framebuffer(&self, framebuffer: u32) {
+        if self.framebuffer.get() != framebuffer {
+            self.framebuffer.set(framebuffer);
+            self.record(Command::BindFramebuffer(framebuffer));
+        }
+    }
+}
+
+impl Context for RecContext {
+    fn get_events(&self) -> Vec<Event> {
+        Vec::new()
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+    fn read_pixels(&self) -> RgbaImage {
+        RgbaImage::new(self.width, self.height)
+    }
+    fn finish(&self) -> Result<(), BackendError> {
+        self.record(Command::Finish);
+        Ok(())
+    }
+}
+
+pub struct RecFrame {
+    context: Rc<RecContext>,
+    /// The `RecFramebuffer` drawn into, 0 for the default one.
+    framebuffer: u32,
+    size: (u32, u32),
+}
+
+impl RecFrame {
+    pub fn new(context: Rc<RecContext>) -> RecFrame {
+        let size = context.dimensions();
+        RecFrame::with_target(context, 0, size)
+    }
+    fn with_target(context: Rc<RecContext>, framebuffer: u32, size: (u32, u32)) -> RecFrame {
+        context.bind_framebuffer(framebuffer);
+        RecFrame {
+            context: context,
+            framebuffer: framebuffer,
+            size: size,
+        }
+    }
+}
+
+impl Frame for RecFrame {
+    fn multi_draw_instanced(&mut self,
+                            program: &BProgram,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        self.context.bind_framebuffer(self.framebuffer);
+        program.multi_draw_instanced(draw_type, vb, ranges, instances, uniforms, params)
+    }
+    fn clear(&mut self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        self.context.bind_framebuffer(self.framebuffer);
+        self.context.record(Command::Clear {
+            color: color,
+            depth: depth,
+            stencil: stencil,
+        });
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+    fn line_width(&self, width: f32) {
+        self.context.record(Command::LineWidth(width));
+    }
+    fn point_size(&self, size: f32) {
+        self.context.record(Command::PointSize(size));
+    }
+    fn read_pixels(&self) -> RgbaImage {
+        self.context.read_pixels()
+    }
+    fn finish(self: Box<Self>) -> Result<(), BackendError> {
+        if self.framebuffer == 0 {
+            try!(self.context.finish());
+        }
+        Ok(())
+    }
+}
+
+pub struct RecProgram {
+    context: Rc<RecContext>,
+    bind: u32,
+    attributes: Vec<ProgramInput>,
+    uniforms: Vec<ProgramInput>,
+    uniform_warnings: UniformWarnings,
+    blocks: Vec<String>,
+}
+
+impl RecProgram {
+    fn from_source(context: &Rc<RecContext>,
+                   vssrc: &str,
+                   fssrc: &str,
+                   gssrc: Option<&str>,
+                   out: &str)
+                   -> Result<RecProgram, BackendError> {
+        let bind = context.gen_bind();
+        context.record(Command::CreateProgram {
+            program: bind,
+            vssrc: String::from(vssrc),
+            fssrc: String::from(fssrc),
+            gssrc: gssrc.map(String::from),
+            out: String::from(out),
+        });
+        let (attributes, uniforms) = glsl::reflect(vssrc, fssrc, gssrc);
+        Ok(RecProgram {
+            context: context.clone(),
+            bind: bind,
+            attributes: attributes,
+            uniforms: uniforms,
+            uniform_warnings: UniformWarnings::new(),
+            blocks: glsl::uniform_blocks(vssrc, fssrc, gssrc),
+        })
+    }
+}
+
+impl Program for RecProgram {
+    fn multi_draw_instanced(&self,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        try!(check_uniforms(self, uniforms, &self.uniform_warnings));
+        try!(check_inputs(self, vb.get_buffers()));
+        try!(check_ranges(&**vb, ranges));
+        try!(check_indices(&**vb));
+        try!(check_instances(&**vb, instances));
+        let vertex_buffer = try!(vb.vertex_array(self));
+        self.context.record(Command::Draw {
+            program: self.bind,
+            draw_type: draw_type,
+            vertex_buffer: vertex_buffer,
+            ranges: ranges.to_vec(),
+            instances: instances,
+            uniforms: uniforms.clone(),
+            params: *params,
+        });
+        Ok(())
+    }
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn attributes(&self) -> &Vec<ProgramInput> {
+        &self.attributes
+    }
+    fn uniforms(&self) -> &Vec<ProgramInput> {
+        &self.uniforms
+    }
+    fn unused_uniforms(&self) -> Vec<String> {
+        self.uniform_warnings.names()
+    }
+    fn bind_uniform_block(&self, name: &str, buffer: &BUniformBuffer) -> Result<(), BackendError> {
+        if !self.blocks.iter().any(|b| b == name) {
+            return Err(BackendError::InvalidUniformBlock { name: String::from(name) });
+        }
+        self.context.record(Command::BindUniformBlock {
+            program: self.bind,
+            block: String::from(name),
+            buffer: buffer.get_bind(),
+        });
+        Ok(())
+    }
+}
+
+pub struct RecBuffer {
+    context: Rc<RecContext>,
+    data: Vec<u8>,
+    layout: VertexLayout,
+    usage: BufferUsage,
+    bind: u32,
+}
+
+impl Buffer for RecBuffer {
+    fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+    fn get_layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), BackendError> {
+        try!(check_write(self.data.len(), offset, data.len()));
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        self.context.record(Command::WriteBuffer {
+            buffer: self.bind,
+            offset: offset,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+    fn set_data(&mut self, data: &[u8]) -> Result<(), BackendError> {
+        try!(self.layout.check(data.len()));
+        self.data = data.to_vec();
+        self.context.record(Command::SetBufferData {
+            buffer: self.bind,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+pub struct RecVertexBuffer {
+    buffers: Vec<BBuffer>,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
+    max_index: Option<u32>,
+    bind: u32,
+}
+
+impl VertexBuffer for RecVertexBuffer {
+    fn get_buffers(&self) -> &Vec<BBuffer> {
+        &self.buffers
+    }
+    fn buffer_mut(&mut self, i: usize) -> &mut Buffer {
+        &mut *self.buffers[i]
+    }
+    fn vertex_array(&self, _program: &Program) -> Result<u32, BackendError> {
+        Ok(self.bind)
+    }
+    fn get_indices(&self) -> Option<&IndexBuffer> {
+        self.indices.as_ref()
+    }
+    fn primitive_restart(&self) -> bool {
+        self.primitive_restart
+    }
+    fn max_index(&self) -> Option<u32> {
+        self.max_index
+    }
+}
+
+pub struct RecVertexBufferBuilder {
+    context: Rc<RecContext>,
+    buffers: Vec<BBuffer>,
+    usage: BufferUsage,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
+}
+
+impl RecVertexBufferBuilder {
+    pub fn new(context: &Rc<RecContext>) -> RecVertexBufferBuilder {
+        RecVertexBufferBuilder {
+            context: context.clone(),
+            buffers: Vec::new(),
+            usage: BufferUsage::Static,
+            indices: None,
+            primitive_restart: false,
+        }
+    }
+}
+
+impl VertexBufferBuilder for RecVertexBufferBuilder {
+    fn add_buffer<T: Copy>(mut self, layout: VertexLayout, data: &[T]) -> RecVertexBufferBuilder {
+        self.buffers.push(Box::new(RecBuffer {
+            context: self.context.clone(),
+            data: slice_as_bytes(data).to_vec(),
+            layout: layout,
+            usage: self.usage,
+            bind: self.context.gen_bind(),
+        }));
+        self
+    }
+    fn usage(mut self, usage: BufferUsage) -> RecVertexBufferBuilder {
+        self.usage = usage;
+        self
+    }
+    fn indices(mut self, indices: IndexBuffer) -> RecVertexBufferBuilder {
+        self.indices = Some(indices);
+        self
+    }
+    fn primitive_restart(mut self, enabled: bool) -> RecVertexBufferBuilder {
+        self.primitive_restart = enabled;
+        self
+    }
+    fn build(self) -> Result<BVertexBuffer, BackendError> {
+        let max_index = try!(check_buffers(&self.buffers,
+                                           self.indices.as_ref(),
+                                           self.primitive_restart));
+        let bind = self.context.gen_bind();
+        self.context.record(Command::CreateVertexBuffer {
+            vertex_buffer: bind,
+            buffers: self.buffers.iter().map(|b| (b.get_bind(), b.usage())).collect(),
+            inputs: self.buffers
+                .iter()
+                .flat_map(|b| {
+                    let layout = b.get_layout();
+                    layout.attributes.iter().map(move |a| (a.name.clone(), b.len(), layout.divisor))
+                })
+                .collect(),
+            indices: self.indices.clone(),
+            primitive_restart: self.primitive_restart,
+        });
+        Ok(Box::new(RecVertexBuffer {
+            buffers: self.buffers,
+            indices: self.indices,
+            primitive_restart: self.primitive_restart,
+            max_index: max_index,
+            bind: bind,
+        }))
+    }
+}
+
+pub struct RecTexture2D {
+    bind: u32,
+    width: u32,
+    height: u32,
+}
+
+impl RecTexture2D {
+    pub fn new(context: &Rc<RecContext>,
+               format: ColorFormat,
+               width: u32,
+               height: u32,
+               data: Vec<u8>)
+               -> Result<RecTexture2D, BackendError> {
+        try!(format.check_data(width, height, &data));
+        let bind = context.gen_bind();
+        context.record(Command::CreateTexture2D {
+            texture: bind,
+            format: format,
+            width: width,
+            height: height,
+        });
+        Ok(RecTexture2D {
+            bind: bind,
+            width: width,
+            height: height,
+        })
+    }
+}
+
+impl Texture2D for RecTexture2D {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+pub struct RecFramebuffer {
+    context: Rc<RecContext>,
+    bind: u32,
+    size: (u32, u32),
+}
+
+impl RecFramebuffer {
+    pub fn new(context: &Rc<RecContext>,
+               colors: &[&BTexture2D],
+               depth: DepthStencil)
+               -> Result<RecFramebuffer, BackendError> {
+        let size = try!(check_attachments(colors));
+        let bind = context.gen_bind();
+        context.record(Command::CreateFramebuffer {
+            framebuffer: bind,
+            colors: colors.iter().map(|t| t.get_bind()).collect(),
+            depth: depth,
+        });
+        Ok(RecFramebuffer {
+            context: context.clone(),
+            bind: bind,
+            size: size,
+        })
+    }
+}
+
+impl Framebuffer for RecFramebuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+    fn frame(&self) -> BFrame {
+        Box::new(RecFrame::with_target(self.context.clone(), self.bind, self.size))
+    }
+}
+
+pub struct RecUniformBuffer {
+    context: Rc<RecContext>,
+    bind: u32,
+    size: usize,
+}
+
+impl RecUniformBuffer {
+    pub fn new(context: &Rc<RecContext>, data: &[u8]) -> Result<RecUniformBuffer, BackendError> {
+        let bind = context.gen_bind();
+        context.record(Command::CreateUniformBuffer {
+            buffer: bind,
+            data: data.to_vec(),
+        });
+        Ok(RecUniformBuffer {
+            context: context.clone(),
+            bind: bind,
+            size: data.len(),
+        })
+    }
+}
+
+impl UniformBuffer for RecUniformBuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn size(&self) -> usize {
+        self.size
+    }
+    fn write(&self, data: &[u8]) -> Result<(), BackendError> {
+        if data.len() != self.size {
+            return Err(BackendError::InvalidBufferSize {
+                expected: self.size,
+                actual: data.len(),
+            });
+        }
+        self.context.record(Command::WriteUniformBuffer {
+            buffer: self.bind,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+}
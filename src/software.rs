@@ -0,0 +1,1532 @@
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ops::{Drop, Range};
+use std::ptr;
+use std::rc::Rc;
+
+use image::{self, RgbaImage};
+
+use super::backend::*;
+use super::error::*;
+use super::event::*;
+use super::parameters::*;
+use super::glsl;
+use super::std140::Std140;
+
+/// Vertex stage of a software program: returns the clip-space position and
+/// the varyings handed (interpolated) to the fragment stage.
+pub type VertexShader = Fn(&Attributes, &ShaderEnv) -> ([f32; 4], Vec<f32>);
+/// Fragment stage of a software program: returns the RGBA output colors, one
+/// per color attachment of the framebuffer drawn into.
+pub type FragmentShader = Fn(&[f32], &ShaderEnv) -> Vec<[f32; 4]>;
+
+/// What a fragment shader closure returns: a single color, or one per color
+/// attachment, in order.
+pub trait FragmentColors {
+    fn into_colors(self) -> Vec<[f32; 4]>;
+}
+
+impl FragmentColors for [f32; 4] {
+    fn into_colors(self) -> Vec<[f32; 4]> {
+        vec![self]
+    }
+}
+
+impl FragmentColors for Vec<[f32; 4]> {
+    fn into_colors(self) -> Vec<[f32; 4]> {
+        self
+    }
+}
+
+impl<const N: usize> FragmentColors for [[f32; 4]; N] {
+    fn into_colors(self) -> Vec<[f32; 4]> {
+        self.to_vec()
+    }
+}
+
+pub struct Software {
+    context: Rc<SWContext>,
+}
+
+impl Software {
+    pub fn new(width: u32, height: u32) -> Software {
+        Software { context: Rc::new(SWContext::new(width, height)) }
+    }
+
+    /// Registers the Rust closures standing in for the GLSL pair `vssrc`/`fssrc`.
+    /// `Facade::program` looks programs up by their sources, so application code
+    /// can pass the same strings it passes to the OpenGL backend.
+    pub fn shader<V, F, C>(&self, vssrc: &str, fssrc: &str, vertex: V, fragment: F)
+        where V: Fn(&Attributes, &ShaderEnv) -> ([f32; 4], Vec<f32>) + 'static,
+              F: Fn(&[f32], &ShaderEnv) -> C + 'static,
+              C: FragmentColors
+    {
+        self.context.shaders.borrow_mut().push(SWShader {
+            vssrc: String::from(vssrc),
+            fssrc: String::from(fssrc),
+            vertex: Rc::new(vertex),
+            fragment: Rc::new(move |varyings: &[f32], env: &ShaderEnv| {
+                fragment(varyings, env).into_colors()
+            }),
+        });
+    }
+}
+
+impl_facade!(Software, context, {
+    Context => SWContext,
+    Frame => SWFrame,
+    Program => SWProgram,
+    VertexBufferBuilder => SWVertexBufferBuilder,
+    Texture2D => SWTexture2D,
+    UniformBuffer => SWUniformBuffer,
+    Framebuffer => SWFramebuffer,
+});
+
+struct SWShader {
+    vssrc: String,
+    fssrc: String,
+    vertex: Rc<VertexShader>,
+    fragment: Rc<FragmentShader>,
+}
+
+pub struct SWContext {
+    width: u32,
+    height: u32,
+    shaders: RefCell<Vec<SWShader>>,
+    textures: RefCell<Vec<Option<SWTextureData>>>,
+    uniform_buffers: RefCell<Vec<Option<Vec<u8>>>>,
+    framebuffers: RefCell<Vec<Option<SWFramebufferData>>>,
+    /// The framebuffer draws and clears go to, 0 for `color`.
+    framebuffer: Cell<u32>,
+    color: RefCell<Vec<u8>>,
+    depth_stencil: RefCell<SWDepthStencil>,
+    line_width: Cell<f32>,
+    point_size: Cell<f32>,
+}
+
+impl SWContext {
+    pub fn new(width: u32, height: u32) -> SWContext {
+        SWContext {
+            width: width,
+            height: height,
+            shaders: RefCell::new(Vec::new()),
+            textures: RefCell::new(Vec::new()),
+            uniform_buffers: RefCell::new(Vec::new()),
+            framebuffers: RefCell::new(Vec::new()),
+            framebuffer: Cell::new(0),
+            color: RefCell::new(vec![0; (width * height * 4) as usize]),
+            depth_stencil: RefCell::new(SWDepthStencil::new((width, height),
+                                                            DepthStencil::DepthStencil)),
+            line_width: Cell::new(1.0),
+            point_size: Cell::new(1.0),
+        }
+    }
+
+    /// Clears the buffers of the current framebuffer that are given a
+    /// value.
+    fn clear(&self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        let clear_color = |rgba: &mut Vec<u8>| if let Some(color) = color {
+            let c = to_rgba8(color);
+            for px in rgba.chunks_mut(4) {
+                px.copy_from_slice(&c);
+            }
+        };
+        match self.framebuffer.get() {
+            0 => {
+                clear_color(&mut self.color.borrow_mut());
+                self.depth_stencil.borrow_mut().clear(depth, stencil);
+            }
+            bind => {
+                let mut framebuffers = self.framebuffers.borrow_mut();
+                if let Some(ref mut framebuffer) = framebuffers[bind as usize - 1] {
+                    let mut textures = self.textures.borrow_mut();
+                    for &color in framebuffer.colors.iter() {
+                        if let Some(ref mut texture) = textures[color as usize - 1] {
+                            clear_color(&mut texture.rgba);
+                        }
+                    }
+                    framebuffer.depth_stencil.clear(depth, stencil);
+                }
+            }
+        }
+    }
+}
+
+/// Depth and stencil values, top row first; empty when the framebuffer has
+/// no such attachment.
+struct SWDepthStencil {
+    depth: Vec<f32>,
+    stencil: Vec<u8>,
+}
+
+impl SWDepthStencil {
+    fn new((width, height): (u32, u32), attachment: DepthStencil) -> SWDepthStencil {
+        let len = (width * height) as usize;
+        let (depth, stencil) = match attachment {
+            DepthStencil::None => (0, 0),
+            DepthStencil::Depth => (len, 0),
+            DepthStencil::DepthStencil => (len, len),
+        };
+        SWDepthStencil {
+            depth: vec![1.0; depth],
+            stencil: vec![0; stencil],
+        }
+    }
+    fn clear(&mut self, depth: Option<f32>, stencil: Option<u8>) {
+        if let Some(depth) = depth {
+            let depth = depth.max(0.0).min(1.0);
+            for d in self.depth.iter_mut() {
+                *d = depth;
+            }
+        }
+        if let Some(stencil) = stencil {
+            for s in self.stencil.iter_mut() {
+                *s = stencil;
+            }
+        }
+    }
+}
+
+impl Context for SWContext {
+    fn get_events(&self) -> Vec<Event> {
+        Vec::new()
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+    fn read_pixels(&self) -> RgbaImage {
+        RgbaImage::from_raw(self.width, self.height, self.color.borrow().clone()).unwrap()
+    }
+    fn finish(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+pub struct SWFrame {
+    context: Rc<SWContext>,
+    /// The `SWFramebuffer` drawn into, 0 for the default one.
+    framebuffer: u32,
+    size: (u32, u32),
+}
+
+impl SWFrame {
+    pub fn new(context: Rc<SWContext>) -> SWFrame {
+        let size = context.dimensions();
+        SWFrame::with_target(context, 0, size)
+    }
+    fn with_target(context: Rc<SWContext>, framebuffer: u32, size: (u32, u32)) -> SWFrame {
+        context.framebuffer.set(framebuffer);
+        SWFrame {
+            context: context,
+            framebuffer: framebuffer,
+            size: size,
+        }
+    }
+}
+
+impl Frame for SWFrame {
+    fn multi_draw_instanced(&mut self,
+                            program: &BProgram,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        self.context.framebuffer.set(self.framebuffer);
+        program.multi_draw_instanced(draw_type, vb, ranges, instances, uniforms, params)
+    }
+    fn clear(&mut self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        self.context.framebuffer.set(self.framebuffer);
+        self.context.clear(color, depth, stencil);
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+    fn line_width(&self, width: f32) {
+        self.context.line_width.set(width);
+    }
+    fn point_size(&self, size: f32) {
+        self.context.point_size.set(size);
+    }
+    fn read_pixels(&self) -> RgbaImage {
+        if self.framebuffer == 0 {
+            return self.context.read_pixels();
+        }
+        let textures = self.context.textures.borrow();
+        let texture = self.context.framebuffers.borrow()[self.framebuffer as usize - 1]
+            .as_ref()
+            .and_then(|framebuffer| textures[framebuffer.colors[0] as usize - 1].as_ref());
+        match texture {
+            // rendered textures hold the bottom row first, as in GL
+            Some(texture) => {
+                let img = RgbaImage::from_raw(texture.width, texture.height, texture.rgba.clone());
+                image::imageops::flip_vertical(&img.unwrap())
+            }
+            None => RgbaImage::new(0, 0),
+        }
+    }
+    fn finish(self: Box<Self>) -> Result<(), BackendError> {
+        if self.framebuffer == 0 {
+            try!(self.context.finish());
+        }
+        Ok(())
+    }
+}
+
+/// Attribute values of the vertex currently being shaded.
+pub struct Attributes<'a> {
+    buffers: &'a Vec<BBuffer>,
+    index: usize,
+    instance: usize,
+}
+
+impl<'a> Attributes<'a> {
+    /// The index of the vertex, `gl_VertexID`.
+    pub fn vertex(&self) -> usize {
+        self.index
+    }
+    /// The index of the instance, `gl_InstanceID`.
+    pub fn instance(&self) -> usize {
+        self.instance
+    }
+    pub fn get(&self, name: &str) -> Option<Vec<f32>> {
+        for buffer in self.buffers.iter() {
+            let layout = buffer.get_layout();
+            if let Some(a) = layout.attributes.iter().find(|a| a.name == name) {
+                let element = match layout.divisor {
+                    0 => self.index,
+                    divisor => self.instance / divisor as usize,
+                };
+                let start = element * layout.stride + a.offset;
+                let size = a.ty.size();
+                return Some((0..a.components)
+                    .map(|i| read_component(a.ty, &buffer.get_data()[start + i * size..]))
+                    .collect());
+            }
+        }
+        None
+    }
+    /// Reads `name` the way GL widens attributes: missing components
+    /// default to (0, 0, 0, 1).
+    pub fn vec4(&self, name: &str) -> [f32; 4] {
+        let mut v = [0.0, 0.0, 0.0, 1.0];
+        if let Some(raw) = self.get(name) {
+            for (d, s) in v.iter_mut().zip(raw.iter()) {
+                *d = *s;
+            }
+        }
+        v
+    }
+    pub fn vec3(&self, name: &str) -> [f32; 3] {
+        let v = self.vec4(name);
+        [v[0], v[1], v[2]]
+    }
+    pub fn vec2(&self, name: &str) -> [f32; 2] {
+        let v = self.vec4(name);
+        [v[0], v[1]]
+    }
+}
+
+/// Reads one component of an attribute as a float.
+fn read_component(ty: AttributeType, bytes: &[u8]) -> f32 {
+    use AttributeType::*;
+    unsafe {
+        match ty {
+            F32 => ptr::read_unaligned(bytes.as_ptr() as *const f32),
+            F16 => half_to_f32(ptr::read_unaligned(bytes.as_ptr() as *const u16)),
+            I32 => ptr::read_unaligned(bytes.as_ptr() as *const i32) as f32,
+            U32 => ptr::read_unaligned(bytes.as_ptr() as *const u32) as f32,
+            U8Norm => bytes[0] as f32 / 255.0,
+            I16Norm => {
+                (ptr::read_unaligned(bytes.as_ptr() as *const i16) as f32 / 32767.0).max(-1.0)
+            }
+        }
+    }
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    sign *
+    match exponent {
+        0 => mantissa * (2.0f32).powi(-24),
+        0x1f if mantissa == 0.0 => ::std::f32::INFINITY,
+        0x1f => ::std::f32::NAN,
+        e => (1.0 + mantissa / 1024.0) * (2.0f32).powi(e - 15),
+    }
+}
+
+/// Uniforms, uniform blocks and textures visible to the shader closures.
+pub struct ShaderEnv<'a> {
+    uniforms: &'a Uniforms<u32>,
+    textures: &'a Vec<Option<SWTextureData>>,
+    blocks: &'a Vec<(String, u32)>,
+    uniform_buffers: &'a Vec<Option<Vec<u8>>>,
+}
+
+impl<'a> ShaderEnv<'a> {
+    pub fn uniform(&self, name: &str) -> Option<&Uniform<u32>> {
+        self.uniforms.get(name)
+    }
+    /// Samples the `Texture2D` uniform `name` with linear filtering and
+    /// repeat wrapping. Unbound samplers read as opaque black, as in GL.
+    pub fn texture(&self, name: &str, s: f32, t: f32) -> [f32; 4] {
+        match self.uniforms.get(name) {
+            Some(&Uniform::Texture2D(bind)) if bind > 0 => {
+                match self.textures.get(bind as usize - 1) {
+                    Some(&Some(ref tex)) => tex.sample(s, t),
+                    _ => [0.0, 0.0, 0.0, 1.0],
+                }
+            }
+            _ => [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+    /// Reads the uniform block `name` as `T`, the type its buffer was
+    /// created from.
+    pub fn block<T: Std140>(&self, name: &str) -> Option<T> {
+        let bind = match self.blocks.iter().find(|&&(ref n, _)| n == name) {
+            Some(&(_, bind)) => bind,
+            None => return None,
+        };
+        match self.uniform_buffers.get(bind as usize - 1) {
+            Some(&Some(ref data)) if data.len() >= mem::size_of::<T>() => {
+                Some(unsafe { ptr::read_unaligned(data.as_ptr() as *const T) })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct SWProgram {
+    context: Rc<SWContext>,
+    vertex: Rc<VertexShader>,
+    fragment: Rc<FragmentShader>,
+    bind: u32,
+    attributes: Vec<ProgramInput>,
+    uniforms: Vec<ProgramInput>,
+    uniform_warnings: UniformWarnings,
+    blocks: Vec<String>,
+    block_bindings: RefCell<Vec<(String, u32)>>,
+}
+
+impl SWProgram {
+    fn from_source(context: &Rc<SWContext>,
+                   vssrc: &str,
+                   fssrc: &str,
+                   gssrc: Option<&str>,
+                   _out: &str)
+                   -> Result<SWProgram, BackendError> {
+        if gssrc.is_some() {
+            return Err(BackendError::Unsupported(String::from("geometry shaders")));
+        }
+        let shaders = context.shaders.borrow();
+        match shaders.iter().position(|s| s.vssrc == vssrc && s.fssrc == fssrc) {
+            Some(i) => {
+                let (attributes, uniforms) = glsl::reflect(vssrc, fssrc, gssrc);
+                Ok(SWProgram {
+                    context: context.clone(),
+                    vertex: shaders[i].vertex.clone(),
+                    fragment: shaders[i].fragment.clone(),
+                    bind: i as u32 + 1,
+                    attributes: attributes,
+                    uniforms: uniforms,
+                    uniform_warnings: UniformWarnings::new(),
+                    blocks: glsl::uniform_blocks(vssrc, fssrc, gssrc),
+                    block_bindings: RefCell::new(Vec::new()),
+                })
+            }
+            None => {
+                Err(BackendError::Unsupported(String::from("GLSL sources without a registered \
+                                                            software shader")))
+            }
+        }
+    }
+
+    fn render(&self,
+              target: &mut Target,
+              draw_type: DrawType,
+              vb: &BVertexBuffer,
+              ranges: &[Range<usize>],
+              instances: usize,
+              uniforms: &Uniforms<u32>) {
+        let textures = self.context.textures.borrow();
+        let blocks = self.block_bindings.borrow();
+        let uniform_buffers = self.context.uniform_buffers.borrow();
+        let env = ShaderEnv {
+            uniforms: uniforms,
+            textures: &textures,
+            blocks: &blocks,
+            uniform_buffers: &uniform_buffers,
+        };
+        let line_width = self.context.line_width.get();
+        let point_size = self.context.point_size.get();
+        let params = target.params;
+        let (referenced, runs) = referenced_vertices(&**vb, ranges);
+        for instance in 0..instances {
+            let vertices: Vec<ClipVertex> = referenced.iter()
+                .map(|&i| {
+                    let attrs = Attributes {
+                        buffers: vb.get_buffers(),
+                        index: i,
+                        instance: instance,
+                    };
+                    let (pos, varyings) = (self.vertex)(&attrs, &env);
+                    ClipVertex {
+                        pos: pos,
+                        varyings: varyings,
+                    }
+                })
+                .collect();
+            for run in runs.iter() {
+                for primitive in assemble(draw_type, run.len()) {
+                    let (triangles, offset) = match primitive {
+                        Primitive::Point(a) => (target.point(&vertices[run[a]], point_size), None),
+                        Primitive::Line(a, b) => {
+                            (target.line(&vertices[run[a]], &vertices[run[b]], line_width), None)
+                        }
+                        Primitive::Triangle(a, b, c) => {
+                            let (a, b) = (&vertices[run[a]], &vertices[run[b]]);
+                            let c = &vertices[run[c]];
+                            if is_culled(a, b, c, params) {
+                                continue;
+                            }
+                            let triangles = match params.polygon_mode {
+                                PolygonMode::Fill => vec![[a.clone(), b.clone(), c.clone()]],
+                                PolygonMode::Line => {
+                                    let mut edges = target.line(a, b, line_width);
+                                    edges.extend(target.line(b, c, line_width));
+                                    edges.extend(target.line(c, a, line_width));
+                                    edges
+                                }
+                                PolygonMode::Point => {
+                                    let mut points = target.point(a, point_size);
+                                    points.extend(target.point(b, point_size));
+                                    points.extend(target.point(c, point_size));
+                                    points
+                                }
+                            };
+                            (triangles, params.polygon_offset)
+                        }
+                    };
+                    for [a, b, c] in triangles {
+                        let polygon = clip_polygon(vec![a, b, c]);
+                        for i in 1..polygon.len().saturating_sub(1) {
+                            target.triangle(&polygon[0],
+                                            &polygon[i],
+                                            &polygon[i + 1],
+                                            offset,
+                                            &*self.fragment,
+                                            &env);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Program for SWProgram {
+    fn multi_draw_instanced(&self,
+                            draw_type: DrawType,
+                            vb: &BVertexBuffer,
+                            ranges: &[Range<usize>],
+                            instances: usize,
+                            uniforms: &Uniforms<u32>,
+                            params: &DrawParameters)
+                            -> Result<(), BackendError> {
+        try!(check_uniforms(self, uniforms, &self.uniform_warnings));
+        try!(check_inputs(self, vb.get_buffers()));
+        try!(check_ranges(&**vb, ranges));
+        try!(check_indices(&**vb));
+        try!(check_instances(&**vb, instances));
+        let framebuffer = self.context.framebuffer.get();
+        if framebuffer == 0 {
+            let mut color = self.context.color.borrow_mut();
+            let mut depth_stencil = self.context.depth_stencil.borrow_mut();
+            let depth_stencil = &mut *depth_stencil;
+            let mut target = Target {
+                width: self.context.width,
+                height: self.context.height,
+                colors: vec![&mut *color],
+                depth: &mut depth_stencil.depth,
+                stencil: &mut depth_stencil.stencil,
+                params: params,
+                bottom_up: false,
+            };
+            self.render(&mut target, draw_type, vb, ranges, instances, uniforms);
+            return Ok(());
+        }
+        let mut framebuffers = self.context.framebuffers.borrow_mut();
+        let framebuffer = match framebuffers[framebuffer as usize - 1] {
+            Some(ref mut framebuffer) => framebuffer,
+            None => return Ok(()),
+        };
+        // taken out while drawing, so sampling them in the same pass reads
+        // black
+        let mut taken: Vec<_> = framebuffer.colors
+            .iter()
+            .map(|&bind| self.context.textures.borrow_mut()[bind as usize - 1].take())
+            .collect();
+        {
+            let mut textures: Vec<_> = taken.iter_mut().filter_map(|t| t.as_mut()).collect();
+            // draws nothing once an attachment is dropped
+            if textures.len() == framebuffer.colors.len() {
+                let (width, height) = (textures[0].width, textures[0].height);
+                let mut target = Target {
+                    width: width,
+                    height: height,
+                    colors: textures.iter_mut().map(|texture| &mut texture.rgba).collect(),
+                    depth: &mut framebuffer.depth_stencil.depth,
+                    stencil: &mut framebuffer.depth_stencil.stencil,
+                    params: params,
+                    bottom_up: true,
+                };
+                self.render(&mut target, draw_type, vb, ranges, instances, uniforms);
+            }
+        }
+        for (&bind, texture) in framebuffer.colors.iter().zip(taken) {
+            if texture.is_some() {
+                self.context.textures.borrow_mut()[bind as usize - 1] = texture;
+            }
+        }
+        Ok(())
+    }
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn attributes(&self) -> &Vec<ProgramInput> {
+        &self.attributes
+    }
+    fn uniforms(&self) -> &Vec<ProgramInput> {
+        &self.uniforms
+    }
+    fn unused_uniforms(&self) -> Vec<String> {
+        self.uniform_warnings.names()
+    }
+    fn bind_uniform_block(&self, name: &str, buffer: &BUniformBuffer) -> Result<(), BackendError> {
+        if !self.blocks.iter().any(|b| b == name) {
+            return Err(BackendError::InvalidUniformBlock { name: String::from(name) });
+        }
+        let mut bindings = self.block_bindings.borrow_mut();
+        bindings.retain(|&(ref n, _)| n != name);
+        bindings.push((String::from(name), buffer.get_bind()));
+        Ok(())
+    }
+}
+
+/// Whether `params` discards the triangle `a`, `b`, `c` for facing the
+/// wrong way.
+fn is_culled(a: &ClipVertex, b: &ClipVertex, c: &ClipVertex, params: &DrawParameters) -> bool {
+    if params.cull == Cull::None {
+        return false;
+    }
+    // the sign of the homogeneous determinant is the winding in GL's
+    // bottom-up window coordinates, counter-clockwise being positive
+    let (a, b, c) = (&a.pos, &b.pos, &c.pos);
+    let det = a[0] * (b[1] * c[3] - c[1] * b[3]) - b[0] * (a[1] * c[3] - c[1] * a[3]) +
+              c[0] * (a[1] * b[3] - b[1] * a[3]);
+    if det == 0.0 {
+        return true;
+    }
+    let front = (det > 0.0) == (params.front_face == Winding::CounterClockwise);
+    match params.cull {
+        Cull::None => false,
+        Cull::Front => front,
+        Cull::Back => !front,
+        Cull::FrontAndBack => true,
+    }
+}
+
+/// The vertices a draw submits, in order, split where the primitive
+/// restarts.
+fn primitive_runs(vb: &VertexBuffer, range: Range<usize>) -> Vec<Vec<usize>> {
+    let indices = match vb.get_indices() {
+        Some(indices) => indices,
+        None => return vec![range.collect()],
+    };
+    let mut runs = vec![Vec::new()];
+    for i in range {
+        let index = indices.get(i);
+        if vb.primitive_restart() && index == indices.restart_index() {
+            runs.push(Vec::new());
+        } else {
+            runs.last_mut().unwrap().push(index as usize);
+        }
+    }
+    runs
+}
+
+/// The vertices the draw of `ranges` references, each once, and its
+/// primitive runs as positions in that list, so that vertices outside the
+/// ranges or unused by the indices are not shaded.
+fn referenced_vertices(vb: &VertexBuffer,
+                       ranges: &[Range<usize>])
+                       -> (Vec<usize>, Vec<Vec<usize>>) {
+    let mut slots: Vec<Option<usize>> = vec![None; vb.len()];
+    let mut referenced = Vec::new();
+    let mut runs = Vec::new();
+    for run in ranges.iter().flat_map(|r| primitive_runs(vb, r.clone())) {
+        runs.push(run.into_iter()
+            .map(|i| {
+                *slots[i].get_or_insert_with(|| {
+                    referenced.push(i);
+                    referenced.len() - 1
+                })
+            })
+            .collect());
+    }
+    (referenced, runs)
+}
+
+#[derive(Debug, PartialEq)]
+enum Primitive {
+    Point(usize),
+    Line(usize, usize),
+    Triangle(usize, usize, usize),
+}
+
+/// Splits `len` vertices into primitives. Adjacency primitives lose their
+/// adjacent vertices, as in GL without a geometry shader.
+fn assemble(draw_type: DrawType, len: usize) -> Vec<Primitive> {
+    use DrawType::*;
+    use self::Primitive::{Point, Line, Triangle};
+    match draw_type {
+        Points => (0..len).map(Point).collect(),
+        Lines => (0..len / 2).map(|i| Line(i * 2, i * 2 + 1)).collect(),
+        LineStrip => (1..len).map(|i| Line(i - 1, i)).collect(),
+        LineLoop => {
+            let mut lines: Vec<_> = (1..len).map(|i| Line(i - 1, i)).collect();
+            if len > 2 {
+                lines.push(Line(len - 1, 0));
+            }
+            lines
+        }
+        Triangles => (0..len / 3).map(|i| Triangle(i * 3, i * 3 + 1, i * 3 + 2)).collect(),
+        TriangleStrip => {
+            (0..len.saturating_sub(2))
+                .map(|i| if i % 2 == 0 {
+                    Triangle(i, i + 1, i + 2)
+                } else {
+                    Triangle(i + 1, i, i + 2)
+                })
+                .collect()
+        }
+        TriangleFan => (1..len.saturating_sub(1)).map(|i| Triangle(0, i, i + 1)).collect(),
+        LinesAdjacency => (0..len / 4).map(|i| Line(i * 4 + 1, i * 4 + 2)).collect(),
+        LineStripAdjacency => (2..len.saturating_sub(1)).map(|i| Line(i - 1, i)).collect(),
+        TrianglesAdjacency => {
+            (0..len / 6).map(|i| Triangle(i * 6, i * 6 + 2, i * 6 + 4)).collect()
+        }
+        TriangleStripAdjacency => {
+            (0..len.saturating_sub(4) / 2)
+                .map(|i| if i % 2 == 0 {
+                    Triangle(i * 2, i * 2 + 2, i * 2 + 4)
+                } else {
+                    Triangle(i * 2 + 2, i * 2, i * 2 + 4)
+                })
+                .collect()
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ClipVertex {
+    pos: [f32; 4],
+    varyings: Vec<f32>,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        let mut pos = [0.0; 4];
+        for i in 0..4 {
+            pos[i] = self.pos[i] + (other.pos[i] - self.pos[i]) * t;
+        }
+        ClipVertex {
+            pos: pos,
+            varyings: self.varyings
+                .iter()
+                .zip(other.varyings.iter())
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+        }
+    }
+}
+
+/// Clips against the near and far planes; x and y are left to the
+/// rasterizer's bounding box.
+fn clip_polygon(mut polygon: Vec<ClipVertex>) -> Vec<ClipVertex> {
+    let planes: [fn(&[f32; 4]) -> f32; 2] = [|p| p[3] + p[2], |p| p[3] - p[2]];
+    for plane in planes.iter() {
+        let mut out = Vec::with_capacity(polygon.len() + 1);
+        for i in 0..polygon.len() {
+            let cur = &polygon[i];
+            let next = &polygon[(i + 1) % polygon.len()];
+            let (dc, dn) = (plane(&cur.pos), plane(&next.pos));
+            if dc >= 0.0 {
+                out.push(cur.clone());
+            }
+            if (dc >= 0.0) != (dn >= 0.0) {
+                out.push(cur.lerp(next, dc / (dc - dn)));
+            }
+        }
+        polygon = out;
+    }
+    polygon
+}
+
+struct Target<'a> {
+    width: u32,
+    height: u32,
+    /// One buffer per color attachment.
+    colors: Vec<&'a mut Vec<u8>>,
+    /// Empty when the target has no depth buffer.
+    depth: &'a mut Vec<f32>,
+    /// Empty when the target has no stencil buffer.
+    stencil: &'a mut Vec<u8>,
+    params: &'a DrawParameters,
+    /// Whether rows are stored bottom first, as in textures.
+    bottom_up: bool,
+}
+
+impl<'a> Target<'a> {
+    fn viewport(&self) -> Rect {
+        self.params.viewport.unwrap_or(Rect::full((self.width, self.height)))
+    }
+    /// The pixels that may be drawn, as `[left, top, right, bottom]` with
+    /// rows counted from the top: the intersection of the target, the
+    /// viewport and the scissor rectangle.
+    fn bounds(&self) -> [f32; 4] {
+        let height = self.height as f32;
+        let mut bounds = [0.0, 0.0, self.width as f32, height];
+        for rect in Some(self.viewport()).iter().chain(self.params.scissor.iter()) {
+            bounds[0] = bounds[0].max(rect.left as f32);
+            bounds[1] = bounds[1].max(height - (rect.bottom + rect.height) as f32);
+            bounds[2] = bounds[2].min((rect.left + rect.width) as f32);
+            bounds[3] = bounds[3].min(height - rect.bottom as f32);
+        }
+        bounds
+    }
+    /// Offsets `v` by `(dx, dy)` pixels, staying in clip space.
+    fn offset(&self, v: &ClipVertex, dx: f32, dy: f32) -> ClipVertex {
+        let viewport = self.viewport();
+        let mut v = v.clone();
+        v.pos[0] += dx * 2.0 / viewport.width as f32 * v.pos[3];
+        v.pos[1] -= dy * 2.0 / viewport.height as f32 * v.pos[3];
+        v
+    }
+    /// The window coordinates of `v`, rows counted from the top.
+    fn to_screen(&self, v: &ClipVertex) -> [f32; 2] {
+        let viewport = self.viewport();
+        let x = (v.pos[0] / v.pos[3] + 1.0) * 0.5 * viewport.width as f32;
+        let y = (v.pos[1] / v.pos[3] + 1.0) * 0.5 * viewport.height as f32;
+        [viewport.left as f32 + x, self.height as f32 - viewport.bottom as f32 - y]
+    }
+    /// A point as a square of `size` pixels.
+    fn point(&self, v: &ClipVertex, size: f32) -> Vec<[ClipVertex; 3]> {
+        if v.pos[3] <= 0.0 {
+            return Vec::new();
+        }
+        let h = size * 0.5;
+        let (a, b) = (self.offset(v, -h, -h), self.offset(v, h, -h));
+        let (c, d) = (self.offset(v, h, h), self.offset(v, -h, h));
+        vec![[a.clone(), b, c.clone()], [a, c, d]]
+    }
+    /// A line as a parallelogram `width` pixels wide.
+    fn line(&self, v0: &ClipVertex, v1: &ClipVertex, width: f32) -> Vec<[ClipVertex; 3]> {
+        if v0.pos[3] <= 0.0 || v1.pos[3] <= 0.0 {
+            return Vec::new();
+        }
+        let (s0, s1) = (self.to_screen(v0), self.to_screen(v1));
+        let (dx, dy) = (s1[0] - s0[0], s1[1] - s0[1]);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            return Vec::new();
+        }
+        let (nx, ny) = (-dy / len * width * 0.5, dx / len * width * 0.5);
+        let (a, b) = (self.offset(v0, nx, ny), self.offset(v0, -nx, -ny));
+        let (c, d) = (self.offset(v1, -nx, -ny), self.offset(v1, nx, ny));
+        vec![[a.clone(), b, c.clone()], [a, c, d]]
+    }
+    fn triangle(&mut self,
+                v0: &ClipVertex,
+                v1: &ClipVertex,
+                v2: &ClipVertex,
+                offset: Option<PolygonOffset>,
+                fragment: &FragmentShader,
+                env: &ShaderEnv) {
+        let mut screen = [[0.0f32; 3]; 3];
+        let mut depth = [0.0f32; 3];
+        for ((s, z), v) in screen.iter_mut().zip(depth.iter_mut()).zip([v0, v1, v2].iter()) {
+            if v.pos[3] <= 0.0 {
+                return;
+            }
+            let [x, y] = self.to_screen(v);
+            *s = [x, y, 1.0 / v.pos[3]];
+            *z = (v.pos[2] / v.pos[3] + 1.0) * 0.5;
+        }
+        let area = edge(&screen[0], &screen[1], &screen[2]);
+        if area == 0.0 {
+            return;
+        }
+        let bias = match offset {
+            Some(offset) => {
+                let (dx1, dy1, dz1) = (screen[1][0] - screen[0][0],
+                                       screen[1][1] - screen[0][1],
+                                       depth[1] - depth[0]);
+                let (dx2, dy2, dz2) = (screen[2][0] - screen[0][0],
+                                       screen[2][1] - screen[0][1],
+                                       depth[2] - depth[0]);
+                let slope = ((dz1 * dy2 - dz2 * dy1) / area)
+                    .abs()
+                    .max(((dx1 * dz2 - dx2 * dz1) / area).abs());
+                offset.factor * slope + offset.units / (1 << 24) as f32
+            }
+            None => 0.0,
+        };
+
+        let [left, top, right, bottom] = self.bounds();
+        let min_x = screen.iter().fold(right, |m, s| m.min(s[0])).max(left) as u32;
+        let min_y = screen.iter().fold(bottom, |m, s| m.min(s[1])).max(top) as u32;
+        let max_x = screen.iter().fold(left, |m, s| m.max(s[0])).ceil().min(right) as u32;
+        let max_y = screen.iter().fold(top, |m, s| m.max(s[1])).ceil().min(bottom) as u32;
+
+        // like GL, a pixel centred exactly on an edge shared by two triangles
+        // is drawn once: by the triangle on the edge's right, or above it if
+        // the edge is horizontal
+        let owns_edge = |a: &[f32; 3], b: &[f32; 3]| {
+            let (dx, dy) = ((b[0] - a[0]) / area, (b[1] - a[1]) / area);
+            dy < 0.0 || (dy == 0.0 && dx < 0.0)
+        };
+        let owned = [owns_edge(&screen[1], &screen[2]),
+                     owns_edge(&screen[2], &screen[0]),
+                     owns_edge(&screen[0], &screen[1])];
+        let inside = |l: f32, owned: bool| l > 0.0 || (l == 0.0 && owned);
+
+        let mut varyings = vec![0.0; v0.varyings.len()];
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+                let l0 = edge(&screen[1], &screen[2], &p) / area;
+                let l1 = edge(&screen[2], &screen[0], &p) / area;
+                let l2 = edge(&screen[0], &screen[1], &p) / area;
+                if !inside(l0, owned[0]) || !inside(l1, owned[1]) || !inside(l2, owned[2]) {
+                    continue;
+                }
+                let row = if self.bottom_up { self.height - 1 - y } else { y };
+                let pixel = (row * self.width + x) as usize;
+                let z = (l0 * depth[0] + l1 * depth[1] + l2 * depth[2] + bias).max(0.0).min(1.0);
+                if !self.test(pixel, z) {
+                    continue;
+                }
+                // perspective-correct weights
+                let (b0, b1, b2) = (l0 * screen[0][2], l1 * screen[1][2], l2 * screen[2][2]);
+                let sum = b0 + b1 + b2;
+                for (i, v) in varyings.iter_mut().enumerate() {
+                    *v = (b0 * v0.varyings[i] + b1 * v1.varyings[i] + b2 * v2.varyings[i]) / sum;
+                }
+                let colors = fragment(&varyings, env);
+                self.write(pixel, &colors);
+            }
+        }
+    }
+    /// Runs the stencil and depth tests of a fragment at `pixel` with
+    /// depth `z`, updating both buffers, and returns whether it passed.
+    fn test(&mut self, pixel: usize, z: f32) -> bool {
+        let stencil = match self.params.stencil {
+            Some(stencil) if !self.stencil.is_empty() => Some(stencil),
+            _ => None,
+        };
+        if let Some(stencil) = stencil {
+            let stored = self.stencil[pixel];
+            let mask = stencil.read_mask;
+            if !stencil.test.compare(stencil.reference & mask, stored & mask) {
+                self.update_stencil(pixel, stencil, stencil.fail);
+                return false;
+            }
+        }
+        let depth = self.params.depth;
+        if depth.is_enabled() && !self.depth.is_empty() {
+            if !depth.test.compare(z, self.depth[pixel]) {
+                if let Some(stencil) = stencil {
+                    self.update_stencil(pixel, stencil, stencil.depth_fail);
+                }
+                return false;
+            }
+            if depth.write {
+                self.depth[pixel] = z;
+            }
+        }
+        if let Some(stencil) = stencil {
+            self.update_stencil(pixel, stencil, stencil.pass);
+        }
+        true
+    }
+    fn update_stencil(&mut self, pixel: usize, stencil: Stencil, op: StencilOp) {
+        let stored = self.stencil[pixel];
+        let updated = op.apply(stored, stencil.reference);
+        self.stencil[pixel] = (stored & !stencil.write_mask) | (updated & stencil.write_mask);
+    }
+    /// Blends `color` into `pixel` and writes the channels of the color
+    /// mask.
+    fn write(&mut self, pixel: usize, colors: &[[f32; 4]]) {
+        let offset = pixel * 4;
+        // attachments the shader has no output for keep their contents
+        for (target, &color) in self.colors.iter_mut().zip(colors.iter()) {
+            let color = match self.params.blend {
+                Some(blend) => {
+                    let mut destination = [0.0; 4];
+                    for (d, &c) in destination.iter_mut().zip(target[offset..offset + 4].iter()) {
+                        *d = c as f32 / 255.0;
+                    }
+                    blend_colors(&blend, clamp(color), destination)
+                }
+                None => color,
+            };
+            let color = to_rgba8(color);
+            for i in 0..4 {
+                if self.params.color_mask[i] {
+                    target[offset + i] = color[i];
+                }
+            }
+        }
+    }
+}
+
+fn clamp(c: [f32; 4]) -> [f32; 4] {
+    let f = |v: f32| v.max(0.0).min(1.0);
+    [f(c[0]), f(c[1]), f(c[2]), f(c[3])]
+}
+
+/// Combines `source` and `destination` by the color equation for red,
+/// green and blue and by the alpha equation for alpha.
+fn blend_colors(blend: &Blend, source: [f32; 4], destination: [f32; 4]) -> [f32; 4] {
+    let apply = |equation: &BlendEquation, i: usize| {
+        let (s, d) = (source[i], destination[i]);
+        let sf = blend_factor(equation.source, i, source, destination, blend.constant);
+        let df = blend_factor(equation.destination, i, source, destination, blend.constant);
+        match equation.op {
+            BlendOp::Add => s * sf + d * df,
+            BlendOp::Subtract => s * sf - d * df,
+            BlendOp::ReverseSubtract => d * df - s * sf,
+            BlendOp::Min => s.min(d),
+            BlendOp::Max => s.max(d),
+        }
+    };
+    clamp([apply(&blend.color, 0),
+           apply(&blend.color, 1),
+           apply(&blend.color, 2),
+           apply(&blend.alpha, 3)])
+}
+
+/// The value of `factor` for channel `i`.
+fn blend_factor(factor: BlendFactor,
+                i: usize,
+                source: [f32; 4],
+                destination: [f32; 4],
+                constant: [f32; 4])
+                -> f32 {
+    use BlendFactor::*;
+    match factor {
+        Zero => 0.0,
+        One => 1.0,
+        SourceColor => source[i],
+        OneMinusSourceColor => 1.0 - source[i],
+        DestinationColor => destination[i],
+        OneMinusDestinationColor => 1.0 - destination[i],
+        SourceAlpha => source[3],
+        OneMinusSourceAlpha => 1.0 - source[3],
+        DestinationAlpha => destination[3],
+        OneMinusDestinationAlpha => 1.0 - destination[3],
+        ConstantColor => constant[i],
+        OneMinusConstantColor => 1.0 - constant[i],
+        ConstantAlpha => constant[3],
+        OneMinusConstantAlpha => 1.0 - constant[3],
+        SourceAlphaSaturate if i == 3 => 1.0,
+        SourceAlphaSaturate => source[3].min(1.0 - destination[3]),
+    }
+}
+
+fn edge(a: &[f32; 3], b: &[f32; 3], p: &[f32; 3]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+fn to_rgba8(c: [f32; 4]) -> [u8; 4] {
+    let f = |v: f32| (v.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+    [f(c[0]), f(c[1]), f(c[2]), f(c[3])]
+}
+
+pub struct SWBuffer {
+    data: Vec<u8>,
+    layout: VertexLayout,
+    usage: BufferUsage,
+}
+
+impl SWBuffer {
+    pub fn new(data: Vec<u8>, layout: VertexLayout, usage: BufferUsage) -> SWBuffer {
+        SWBuffer {
+            data: data,
+            layout: layout,
+            usage: usage,
+        }
+    }
+}
+
+impl Buffer for SWBuffer {
+    fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+    fn get_layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+    fn get_bind(&self) -> u32 {
+        0
+    }
+    fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), BackendError> {
+        try!(check_write(self.data.len(), offset, data.len()));
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+    fn set_data(&mut self, data: &[u8]) -> Result<(), BackendError> {
+        try!(self.layout.check(data.len()));
+        self.data = data.to_vec();
+        Ok(())
+    }
+}
+
+pub struct SWVertexBuffer {
+    buffers: Vec<BBuffer>,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
+    max_index: Option<u32>,
+}
+
+impl VertexBuffer for SWVertexBuffer {
+    fn get_buffers(&self) -> &Vec<BBuffer> {
+        &self.buffers
+    }
+    fn buffer_mut(&mut self, i: usize) -> &mut Buffer {
+        &mut *self.buffers[i]
+    }
+    fn vertex_array(&self, _program: &Program) -> Result<u32, BackendError> {
+        Ok(0)
+    }
+    fn get_indices(&self) -> Option<&IndexBuffer> {
+        self.indices.as_ref()
+    }
+    fn primitive_restart(&self) -> bool {
+        self.primitive_restart
+    }
+    fn max_index(&self) -> Option<u32> {
+        self.max_index
+    }
+}
+
+pub struct SWVertexBufferBuilder {
+    buffers: Vec<BBuffer>,
+    usage: BufferUsage,
+    indices: Option<IndexBuffer>,
+    primitive_restart: bool,
+}
+
+impl SWVertexBufferBuilder {
+    pub fn new(_context: &Rc<SWContext>) -> SWVertexBufferBuilder {
+        SWVertexBufferBuilder {
+            buffers: Vec::new(),
+            usage: BufferUsage::Static,
+            indices: None,
+            primitive_restart: false,
+        }
+    }
+}
+
+impl VertexBufferBuilder for SWVertexBufferBuilder {
+    fn add_buffer<T: Copy>(mut self, layout: VertexLayout, data: &[T]) -> SWVertexBufferBuilder {
+        self.buffers.push(Box::new(SWBuffer::new(slice_as_bytes(data).to_vec(),
+                                                 layout,
+                                                 self.usage)));
+        self
+    }
+    fn usage(mut self, usage: BufferUsage) -> SWVertexBufferBuilder {
+        self.usage = usage;
+        self
+    }
+    fn indices(mut self, indices: IndexBuffer) -> SWVertexBufferBuilder {
+        self.indices = Some(indices);
+        self
+    }
+    fn primitive_restart(mut self, enabled: bool) -> SWVertexBufferBuilder {
+        self.primitive_restart = enabled;
+        self
+    }
+    fn build(self) -> Result<BVertexBuffer, BackendError> {
+        let max_index = try!(check_buffers(&self.buffers,
+                                           self.indices.as_ref(),
+                                           self.primitive_restart));
+        Ok(Box::new(SWVertexBuffer {
+            buffers: self.buffers,
+            indices: self.indices,
+            primitive_restart: self.primitive_restart,
+            max_index: max_index,
+        }))
+    }
+}
+
+struct SWTextureData {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl SWTextureData {
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (x, y) = (((x % w) + w) % w, ((y % h) + h) % h);
+        let offset = ((y * w + x) * 4) as usize;
+        let mut c = [0.0; 4];
+        for i in 0..4 {
+            c[i] = self.rgba[offset + i] as f32 / 255.0;
+        }
+        c
+    }
+    fn sample(&self, s: f32, t: f32) -> [f32; 4] {
+        let x = s * self.width as f32 - 0.5;
+        let y = t * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let (c00, c10) = (self.texel(x0, y0), self.texel(x0 + 1, y0));
+        let (c01, c11) = (self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1));
+        let mut c = [0.0; 4];
+        for i in 0..4 {
+            let top = c00[i] + (c10[i] - c00[i]) * fx;
+            let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+            c[i] = top + (bottom - top) * fy;
+        }
+        c
+    }
+}
+
+pub struct SWTexture2D {
+    context: Rc<SWContext>,
+    bind: u32,
+    width: u32,
+    height: u32,
+}
+
+impl SWTexture2D {
+    pub fn new(context: &Rc<SWContext>,
+               format: ColorFormat,
+               width: u32,
+               height: u32,
+               data: Vec<u8>)
+               -> Result<SWTexture2D, BackendError> {
+        try!(format.check_data(width, height, &data));
+        let rgba = match format {
+            ColorFormat::RGBA => data,
+            ColorFormat::RGB => {
+                data.chunks(3)
+                    .flat_map(|c| vec![c[0], c[1], c[2], 255])
+                    .collect()
+            }
+        };
+        let mut textures = context.textures.borrow_mut();
+        textures.push(Some(SWTextureData {
+            width: width,
+            height: height,
+            rgba: rgba,
+        }));
+        Ok(SWTexture2D {
+            context: context.clone(),
+            bind: textures.len() as u32,
+            width: width,
+            height: height,
+        })
+    }
+}
+
+impl Texture2D for SWTexture2D {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for SWTexture2D {
+    fn drop(&mut self) {
+        self.context.textures.borrow_mut()[self.bind as usize - 1] = None;
+    }
+}
+
+struct SWFramebufferData {
+    /// The binds of the color textures, in attachment order.
+    colors: Vec<u32>,
+    depth_stencil: SWDepthStencil,
+}
+
+pub struct SWFramebuffer {
+    context: Rc<SWContext>,
+    bind: u32,
+    size: (u32, u32),
+}
+
+impl SWFramebuffer {
+    pub fn new(context: &Rc<SWContext>,
+               colors: &[&BTexture2D],
+               depth: DepthStencil)
+               -> Result<SWFramebuffer, BackendError> {
+        let size = try!(check_attachments(colors));
+        let mut framebuffers = context.framebuffers.borrow_mut();
+        framebuffers.push(Some(SWFramebufferData {
+            colors: colors.iter().map(|texture| texture.get_bind()).collect(),
+            depth_stencil: SWDepthStencil::new(size, depth),
+        }));
+        Ok(SWFramebuffer {
+            context: context.clone(),
+            bind: framebuffers.len() as u32,
+            size: size,
+        })
+    }
+}
+
+impl Framebuffer for SWFramebuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        self.size
+    }
+    fn frame(&self) -> BFrame {
+        Box::new(SWFrame::with_target(self.context.clone(), self.bind, self.size))
+    }
+}
+
+impl Drop for SWFramebuffer {
+    fn drop(&mut self) {
+        self.context.framebuffers.borrow_mut()[self.bind as usize - 1] = None;
+    }
+}
+
+pub struct SWUniformBuffer {
+    context: Rc<SWContext>,
+    bind: u32,
+    size: usize,
+}
+
+impl SWUniformBuffer {
+    pub fn new(context: &Rc<SWContext>, data: &[u8]) -> Result<SWUniformBuffer, BackendError> {
+        let mut buffers = context.uniform_buffers.borrow_mut();
+        buffers.push(Some(data.to_vec()));
+        Ok(SWUniformBuffer {
+            context: context.clone(),
+            bind: buffers.len() as u32,
+            size: data.len(),
+        })
+    }
+}
+
+impl UniformBuffer for SWUniformBuffer {
+    fn get_bind(&self) -> u32 {
+        self.bind
+    }
+    fn size(&self) -> usize {
+        self.size
+    }
+    fn write(&self, data: &[u8]) -> Result<(), BackendError> {
+        if data.len() != self.size {
+            return Err(BackendError::InvalidBufferSize {
+                expected: self.size,
+                actual: data.len(),
+            });
+        }
+        self.context.uniform_buffers.borrow_mut()[self.bind as usize - 1] = Some(data.to_vec());
+        Ok(())
+    }
+}
+
+impl Drop for SWUniformBuffer {
+    fn drop(&mut self) {
+        self.context.uniform_buffers.borrow_mut()[self.bind as usize - 1] = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use super::*;
+    use super::super::backend::slice_as_bytes;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x3555), 0.333251953125);
+        // subnormals have no implicit leading one
+        assert_eq!(half_to_f32(0x0001), 2.0f32.powi(-24));
+        assert_eq!(half_to_f32(0x03ff), 1023.0 * 2.0f32.powi(-24));
+        assert_eq!(half_to_f32(0x8000), 0.0);
+        assert!(half_to_f32(0x8000).is_sign_negative());
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+        assert!(half_to_f32(0xfc01).is_nan());
+    }
+
+    #[test]
+    fn components_of_each_type() {
+        use AttributeType::*;
+
+        assert_eq!(read_component(F32, slice_as_bytes(&[1.5f32])), 1.5);
+        assert_eq!(read_component(F16, slice_as_bytes(&[0xbc00u16])), -1.0);
+        assert_eq!(read_component(I32, slice_as_bytes(&[-7i32])), -7.0);
+        assert_eq!(read_component(U32, slice_as_bytes(&[7u32])), 7.0);
+        // unaligned reads, as interleaved vertices give them
+        assert_eq!(read_component(F32, &slice_as_bytes(&[0u8, 0, 0, 0xc0, 0x3f])[1..]), 1.5);
+    }
+
+    #[test]
+    fn normalized_components() {
+        use AttributeType::*;
+
+        assert_eq!(read_component(U8Norm, &[0]), 0.0);
+        assert_eq!(read_component(U8Norm, &[51]), 0.2);
+        assert_eq!(read_component(U8Norm, &[255]), 1.0);
+        assert_eq!(read_component(I16Norm, slice_as_bytes(&[32767i16])), 1.0);
+        assert_eq!(read_component(I16Norm, slice_as_bytes(&[0i16])), 0.0);
+        // both of the two most negative values map to -1.0
+        assert_eq!(read_component(I16Norm, slice_as_bytes(&[-32767i16])), -1.0);
+        assert_eq!(read_component(I16Norm, slice_as_bytes(&[-32768i16])), -1.0);
+    }
+
+    #[test]
+    fn primitive_assembly() {
+        use DrawType::*;
+        use super::Primitive::{Point, Line, Triangle};
+
+        assert_eq!(assemble(Points, 2), vec![Point(0), Point(1)]);
+        // incomplete primitives are dropped
+        assert_eq!(assemble(Lines, 5), vec![Line(0, 1), Line(2, 3)]);
+        assert_eq!(assemble(LineStrip, 3), vec![Line(0, 1), Line(1, 2)]);
+        assert_eq!(assemble(LineLoop, 3), vec![Line(0, 1), Line(1, 2), Line(2, 0)]);
+        assert_eq!(assemble(LineLoop, 2), vec![Line(0, 1)]);
+        assert_eq!(assemble(Triangles, 7), vec![Triangle(0, 1, 2), Triangle(3, 4, 5)]);
+        // every other strip triangle is flipped to keep the winding
+        assert_eq!(assemble(TriangleStrip, 5),
+                   vec![Triangle(0, 1, 2), Triangle(2, 1, 3), Triangle(2, 3, 4)]);
+        assert_eq!(assemble(TriangleFan, 5),
+                   vec![Triangle(0, 1, 2), Triangle(0, 2, 3), Triangle(0, 3, 4)]);
+        assert_eq!(assemble(TriangleStrip, 2), vec![]);
+        assert_eq!(assemble(TriangleFan, 0), vec![]);
+    }
+
+    #[test]
+    fn adjacency_assembly() {
+        use DrawType::*;
+        use super::Primitive::{Line, Triangle};
+
+        assert_eq!(assemble(LinesAdjacency, 8), vec![Line(1, 2), Line(5, 6)]);
+        assert_eq!(assemble(LineStripAdjacency, 5), vec![Line(1, 2), Line(2, 3)]);
+        assert_eq!(assemble(TrianglesAdjacency, 6), vec![Triangle(0, 2, 4)]);
+        assert_eq!(assemble(TriangleStripAdjacency, 8),
+                   vec![Triangle(0, 2, 4), Triangle(4, 2, 6)]);
+    }
+
+    #[test]
+    fn only_referenced_vertices_are_shaded() {
+        let facade = Software::new(8, 8);
+        let shaded = Rc::new(RefCell::new(Vec::new()));
+        let log = shaded.clone();
+        facade.shader(VS_SRC,
+                      FS_SRC,
+                      move |attrs, _| {
+                          log.borrow_mut().push((attrs.instance(), attrs.vertex()));
+                          ([0.0, 0.0, 0.0, 1.0], Vec::new())
+                      },
+                      |_, _| [1.0; 4]);
+        let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+        let position = || InputBuffer::Vec2(vec![0.0; 12]);
+        let vb = facade.vertex_buffer().add_input("position", position()).build().unwrap();
+        let indexed = facade.vertex_buffer()
+            .add_input("position", position())
+            .indices(IndexBuffer::U8(vec![4, 1, 4, 1, 0, 4]))
+            .build()
+            .unwrap();
+        let uniforms = Uniforms::new();
+        let params = Default::default();
+        let mut frame = facade.frame();
+
+        frame.multi_draw(&program, DrawType::Points, &vb, &[1..3, 2..4], &uniforms, &params)
+            .unwrap();
+        assert_eq!(shaded.borrow_mut().split_off(0), vec![(0, 1), (0, 2), (0, 3)]);
+
+        frame.draw_instanced(&program, DrawType::Triangles, &indexed, 2, &uniforms, &params)
+            .unwrap();
+        assert_eq!(shaded.borrow_mut().split_off(0),
+                   vec![(0, 4), (0, 1), (0, 0), (1, 4), (1, 1), (1, 0)]);
+        frame.finish().unwrap();
+    }
+
+    static VS_SRC: &'static str = "in vec2 position; void main() {}";
+    static FS_SRC: &'static str = "out vec4 out_color; void main() {}";
+
+    /// Draws white on an 8x8 black frame and returns the pixels covered,
+    /// as `(x, y)` with rows counted from the top.
+    fn coverage(draw_type: DrawType, positions: Vec<f32>, size: f32) -> Vec<(u32, u32)> {
+        let facade = Software::new(8, 8);
+        facade.shader(VS_SRC,
+                      FS_SRC,
+                      |attrs, _| {
+                          let p = attrs.vec2("position");
+                          ([p[0], p[1], 0.0, 1.0], Vec::new())
+                      },
+                      |_, _| [1.0; 4]);
+        let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+        let vb = facade.vertex_buffer()
+            .add_input("position", InputBuffer::Vec2(positions))
+            .build()
+            .unwrap();
+        let mut frame = facade.frame();
+        frame.clear(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+        frame.point_size(size);
+        frame.line_width(size);
+        frame.draw(&program, draw_type, &vb, &Uniforms::new(), &Default::default()).unwrap();
+        let pixels = frame.read_pixels();
+        frame.finish().unwrap();
+        pixels.enumerate_pixels()
+            .filter(|&(_, _, p)| p.data[0] > 0)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    fn square(left: u32, top: u32, right: u32, bottom: u32) -> Vec<(u32, u32)> {
+        (top..bottom + 1).flat_map(|y| (left..right + 1).map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn points_as_squares() {
+        // centred on pixel (3, 3)
+        assert_eq!(coverage(DrawType::Points, vec![-0.125, 0.125], 1.0), vec![(3, 3)]);
+        assert_eq!(coverage(DrawType::Points, vec![-0.125, 0.125], 3.0), square(2, 2, 4, 4));
+        // halfway between pixels an even size covers whole pixels
+        assert_eq!(coverage(DrawType::Points, vec![0.0, 0.0], 2.0), square(3, 3, 4, 4));
+    }
+
+    #[test]
+    fn lines_as_quads() {
+        // from x = 1 to x = 7 along the centre of row 3
+        let horizontal = vec![-0.75, 0.125, 0.75, 0.125];
+        assert_eq!(coverage(DrawType::Lines, horizontal.clone(), 1.0), square(1, 3, 6, 3));
+        assert_eq!(coverage(DrawType::Lines, horizontal, 3.0), square(1, 2, 6, 4));
+        let vertical = vec![0.125, -0.75, 0.125, 0.75];
+        assert_eq!(coverage(DrawType::Lines, vertical, 1.0), square(4, 1, 4, 6));
+        assert_eq!(coverage(DrawType::Lines, vec![0.0, 0.0, 0.0, 0.0], 1.0), vec![]);
+    }
+}
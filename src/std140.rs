@@ -0,0 +1,134 @@
+use std::mem;
+use std::slice;
+
+/// A type with a known std140 layout that can be uploaded to a
+/// `UniformBuffer` as is.
+///
+/// GLSL types map to `f32`, `i32`, `u32` (also for `bool`), `[T; 2..4]` for
+/// vectors and `[[f32; 4]; C]` for matrices of C columns, each column padded
+/// to a `vec4` and given column by column. Array elements are wrapped in
+/// `Padded` to get their 16 byte stride. Structs implement it through
+/// `std140_block!`.
+pub unsafe trait Std140: Copy {
+    const ALIGN: usize;
+    const SIZE: usize;
+    /// Writes the value into the first `SIZE` bytes of `out`, leaving the
+    /// padding as it is.
+    fn write_std140(&self, out: &mut [u8]);
+}
+
+macro_rules! impl_std140 {
+    ($($ty:ty => ($align:expr, $size:expr)),*) => (
+        $(unsafe impl Std140 for $ty {
+            const ALIGN: usize = $align;
+            const SIZE: usize = $size;
+            fn write_std140(&self, out: &mut [u8]) {
+                // scalars, vectors and matrices have no padding of their own
+                let bytes = unsafe {
+                    slice::from_raw_parts(self as *const $ty as *const u8, mem::size_of::<$ty>())
+                };
+                out[..bytes.len()].copy_from_slice(bytes);
+            }
+        })*
+    );
+}
+
+impl_std140!(f32 => (4, 4), i32 => (4, 4), u32 => (4, 4),
+             [f32; 2] => (8, 8), [i32; 2] => (8, 8), [u32; 2] => (8, 8),
+             [f32; 3] => (16, 12), [i32; 3] => (16, 12), [u32; 3] => (16, 12),
+             [f32; 4] => (16, 16), [i32; 4] => (16, 16), [u32; 4] => (16, 16),
+             [[f32; 4]; 2] => (16, 32), [[f32; 4]; 3] => (16, 48), [[f32; 4]; 4] => (16, 64));
+
+/// An array element, aligned to the 16 byte stride std140 gives arrays.
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Padded<T: Copy>(pub T);
+
+unsafe impl<T: Std140> Std140 for Padded<T> {
+    const ALIGN: usize = 16;
+    const SIZE: usize = align_to(T::SIZE, 16);
+    fn write_std140(&self, out: &mut [u8]) {
+        self.0.write_std140(out);
+    }
+}
+
+unsafe impl<T: Std140, const N: usize> Std140 for [Padded<T>; N] {
+    const ALIGN: usize = 16;
+    const SIZE: usize = align_to(T::SIZE, 16) * N;
+    fn write_std140(&self, out: &mut [u8]) {
+        for (elem, out) in self.iter().zip(out.chunks_mut(Padded::<T>::SIZE)) {
+            elem.write_std140(out);
+        }
+    }
+}
+
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+/// Lays `data` out as std140 with zeroed padding, which reading the value's
+/// own memory would leave uninitialised.
+pub fn to_bytes<T: Std140>(data: &T) -> Vec<u8> {
+    let mut bytes = vec![0; T::SIZE];
+    data.write_std140(&mut bytes);
+    bytes
+}
+
+/// Declares a `#[repr(C)]` struct for a uniform block and implements
+/// `Std140` for it. Each field is checked at compile time against the offset
+/// std140 gives it, and the size against the padded block size, so missing
+/// padding fails the build instead of corrupting the block:
+///
+/// ```ignore
+/// std140_block! {
+///     pub struct Camera {
+///         pub view: [[f32; 4]; 4],
+///         pub position: [f32; 3],
+///         pub time: f32,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! std140_block {
+    ($(#[$attr:meta])* $svis:vis struct $name:ident {
+        $($fvis:vis $field:ident : $ty:ty),* $(,)*
+    }) => (
+        $(#[$attr])*
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        $svis struct $name {
+            $($fvis $field: $ty),*
+        }
+
+        unsafe impl $crate::std140::Std140 for $name {
+            const ALIGN: usize = 16;
+            const SIZE: usize = {
+                let mut end = 0;
+                $(end = $crate::std140::align_to(end, <$ty as $crate::std140::Std140>::ALIGN) +
+                        <$ty as $crate::std140::Std140>::SIZE;)*
+                $crate::std140::align_to(end, 16)
+            };
+            fn write_std140(&self, out: &mut [u8]) {
+                $(<$ty as $crate::std140::Std140>::write_std140(
+                    &self.$field, &mut out[::std::mem::offset_of!($name, $field)..]);)*
+            }
+        }
+
+        const _: () = {
+            let mut offset = 0;
+            $(
+                offset = $crate::std140::align_to(offset,
+                                                  <$ty as $crate::std140::Std140>::ALIGN);
+                assert!(offset == ::std::mem::offset_of!($name, $field),
+                        concat!("`", stringify!($name), "::", stringify!($field),
+                                "` is not at its std140 offset, pad the fields before it"));
+                offset += <$ty as $crate::std140::Std140>::SIZE;
+            )*
+            let _ = offset;
+            assert!(::std::mem::size_of::<$name>() ==
+                    <$name as $crate::std140::Std140>::SIZE,
+                    concat!("`", stringify!($name),
+                            "` must be padded to a multiple of 16 bytes"));
+        };
+    );
+}
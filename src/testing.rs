@@ -0,0 +1,206 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::{self, Rgba, RgbaImage};
+
+use super::backend::*;
+use super::error::BackendError;
+use super::opengl::OpenGL;
+
+/// Setting this environment variable (re)writes missing or mismatching
+/// references from the rendered output instead of failing.
+pub const BLESS_VAR: &'static str = "GRAPHIC_BACKEND_BLESS";
+
+/// Tests drawing with OpenGL only run when this environment variable is set,
+/// as they need a driver that can create headless contexts.
+pub const OPENGL_VAR: &'static str = "GRAPHIC_BACKEND_OPENGL";
+
+/// Creates a headless context for a test, or returns `None` if `OPENGL_VAR`
+/// is not set and the test should be skipped.
+pub fn headless_opengl(width: u32, height: u32) -> Option<OpenGL> {
+    if env::var_os(OPENGL_VAR).is_none() {
+        return None;
+    }
+    match OpenGL::headless(width, height) {
+        Ok(facade) => Some(facade),
+        Err(e) => panic!("{} is set but no headless context could be created: {}", OPENGL_VAR, e),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// Largest accepted difference per color channel.
+    pub channel: u8,
+    /// Number of pixels allowed to exceed `channel`.
+    pub max_differing_pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance {
+            channel: 2,
+            max_differing_pixels: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Backend(BackendError),
+    MissingReference(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        differing_pixels: usize,
+        diff: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::GoldenError::*;
+        match self {
+            &Io(ref e) => write!(f, "{}", e),
+            &Image(ref e) => write!(f, "{}", e),
+            &Backend(ref e) => write!(f, "{}", e),
+            &MissingReference(ref p) => {
+                write!(f,
+                       "reference {} does not exist (set {} to create it)",
+                       p.display(),
+                       BLESS_VAR)
+            }
+            &SizeMismatch { expected, actual } => {
+                write!(f,
+                       "expected a {}x{} image, rendered {}x{}",
+                       expected.0,
+                       expected.1,
+                       actual.0,
+                       actual.1)
+            }
+            &Mismatch { differing_pixels, ref diff } => {
+                write!(f,
+                       "{} pixels differ from the reference, see {}",
+                       differing_pixels,
+                       diff.display())
+            }
+        }
+    }
+}
+
+impl Error for GoldenError {
+    fn description(&self) -> &str {
+        "rendered image does not match its reference"
+    }
+}
+
+impl From<io::Error> for GoldenError {
+    fn from(e: io::Error) -> GoldenError {
+        GoldenError::Io(e)
+    }
+}
+
+impl From<BackendError> for GoldenError {
+    fn from(e: BackendError) -> GoldenError {
+        GoldenError::Backend(e)
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(e: image::ImageError) -> GoldenError {
+        GoldenError::Image(e)
+    }
+}
+
+/// Counts the pixels of `actual` differing from `expected`, which must be
+/// of the same size, by more than `tolerance.channel` and returns them with
+/// an image highlighting them in red.
+fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: &Tolerance) -> (usize, RgbaImage) {
+    let mut differing = 0;
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    for (x, y, a) in actual.enumerate_pixels() {
+        let e = expected.get_pixel(x, y);
+        let over = a.data
+            .iter()
+            .zip(e.data.iter())
+            .any(|(a, e)| (*a as i16 - *e as i16).abs() > tolerance.channel as i16);
+        if over {
+            differing += 1;
+            diff.put_pixel(x, y, Rgba { data: [255, 0, 0, 255] });
+        } else {
+            let l = ((e.data[0] as u32 + e.data[1] as u32 + e.data[2] as u32) / 9) as u8;
+            diff.put_pixel(x, y, Rgba { data: [l, l, l, 255] });
+        }
+    }
+    (differing, diff)
+}
+
+/// Checks `actual` against the PNG at `reference`. On failure the rendered
+/// image and the diff are written next to the reference as
+/// `<name>.actual.png` and `<name>.diff.png`.
+pub fn check_image<P: AsRef<Path>>(actual: &RgbaImage,
+                                   reference: P,
+                                   tolerance: &Tolerance)
+                                   -> Result<(), GoldenError> {
+    let reference = reference.as_ref();
+    let bless = env::var_os(BLESS_VAR).is_some();
+    if !reference.exists() {
+        if bless {
+            return Ok(try!(actual.save(reference)));
+        }
+        return Err(GoldenError::MissingReference(reference.to_path_buf()));
+    }
+
+    let expected = try!(image::open(reference)).to_rgba();
+    let failure = if expected.dimensions() != actual.dimensions() {
+        Some(GoldenError::SizeMismatch {
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        })
+    } else {
+        let (differing, diff) = compare(actual, &expected, tolerance);
+        if differing > tolerance.max_differing_pixels {
+            let diff_path = reference.with_extension("diff.png");
+            try!(diff.save(&diff_path));
+            Some(GoldenError::Mismatch {
+                differing_pixels: differing,
+                diff: diff_path,
+            })
+        } else {
+            None
+        }
+    };
+
+    match failure {
+        Some(_) if bless => Ok(try!(actual.save(reference))),
+        Some(e) => {
+            try!(actual.save(reference.with_extension("actual.png")));
+            Err(e)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Draws `scene` into a fresh frame of `facade`, reads it back and checks it
+/// against `reference`.
+pub fn check_scene<F, P, S>(facade: &F,
+                            reference: P,
+                            tolerance: &Tolerance,
+                            scene: S)
+                            -> Result<(), GoldenError>
+    where F: Facade,
+          P: AsRef<Path>,
+          S: FnOnce(&F, &mut BFrame)
+{
+    let mut frame = facade.frame();
+    scene(facade, &mut frame);
+    let actual = frame.read_pixels();
+    try!(frame.finish());
+    check_image(&actual, reference, tolerance)
+}
@@ -0,0 +1,113 @@
+extern crate graphic_backend;
+
+use graphic_backend::ShaderStage;
+use graphic_backend::diagnostics::*;
+
+static SOURCE: &'static str = "#version 150
+in vec3 position
+out vec2 coord0;
+void main() {
+    gl_Position = vec4(position, 1.0);
+}";
+
+fn context(lines: &[usize]) -> Vec<(usize, String)> {
+    let source: Vec<&str> = SOURCE.lines().collect();
+    lines.iter().map(|&n| (n, String::from(source[n - 1]))).collect()
+}
+
+#[test]
+fn mesa_log() {
+    let log = "0:3(1): error: syntax error, unexpected OUT, expecting ',' or ';'\n";
+    assert_eq!(parse_log(log, SOURCE),
+               vec![Diagnostic {
+                        severity: Severity::Error,
+                        line: Some(3),
+                        column: Some(1),
+                        message: String::from("syntax error, unexpected OUT, expecting ',' or ';'"),
+                        source_line: Some(String::from("out vec2 coord0;")),
+                        context: context(&[1, 2, 3, 4, 5]),
+                    }]);
+}
+
+#[test]
+fn nvidia_log() {
+    let log = "0(3) : error C0000: syntax error, unexpected \"out\"\n\
+               0(5) : warning C7533: global variable gl_Position is deprecated";
+    let diagnostics = parse_log(log, SOURCE);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert_eq!(diagnostics[0].column, None);
+    assert_eq!(diagnostics[0].message, "syntax error, unexpected \"out\"");
+    assert_eq!(diagnostics[1].severity, Severity::Warning);
+    assert_eq!(diagnostics[1].line, Some(5));
+    assert_eq!(diagnostics[1].message, "global variable gl_Position is deprecated");
+    // the context stops at the last line of the source
+    assert_eq!(diagnostics[1].context, context(&[3, 4, 5, 6]));
+}
+
+#[test]
+fn amd_log() {
+    let log = "ERROR: 0:3: 'out' : syntax error syntax error\n\
+               ERROR: 1 compilation errors.  No code generated.";
+    let diagnostics = parse_log(log, SOURCE);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert_eq!(diagnostics[0].message, "'out' : syntax error syntax error");
+    assert_eq!(diagnostics[0].source_line, Some(String::from("out vec2 coord0;")));
+    // the summary line has no location
+    assert_eq!(diagnostics[1].line, None);
+    assert_eq!(diagnostics[1].message,
+               "ERROR: 1 compilation errors.  No code generated.");
+}
+
+#[test]
+fn unparseable_log_line() {
+    let log = "Warning: shader compiled with a fallback path\n\n";
+    assert_eq!(parse_log(log, SOURCE),
+               vec![Diagnostic {
+                        severity: Severity::Warning,
+                        line: None,
+                        column: None,
+                        message: String::from("Warning: shader compiled with a fallback path"),
+                        source_line: None,
+                        context: Vec::new(),
+                    }]);
+}
+
+#[test]
+fn line_past_the_end_of_the_source() {
+    let diagnostics = parse_log("0:42(1): error: unexpected end of file", SOURCE);
+    assert_eq!(diagnostics[0].line, Some(42));
+    assert_eq!(diagnostics[0].source_line, None);
+    assert_eq!(diagnostics[0].context, Vec::new());
+}
+
+#[test]
+fn pretty_printed_like_rustc() {
+    let diagnostics = parse_log("0:3(5): error: syntax error, unexpected IDENTIFIER\n\
+                                 0(2) : warning C0000: missing semicolon",
+                                SOURCE);
+    assert_eq!(pretty_print(ShaderStage::Vertex, &diagnostics),
+               "error: syntax error, unexpected IDENTIFIER
+  --> vertex shader:3:5
+  |
+1 | #version 150
+2 | in vec3 position
+3 | out vec2 coord0;
+  |     ^
+4 | void main() {
+5 |     gl_Position = vec4(position, 1.0);
+
+warning: missing semicolon
+  --> vertex shader:2
+  |
+1 | #version 150
+2 | in vec3 position
+  | ^^^^^^^^^^^^^^^^
+3 | out vec2 coord0;
+4 | void main() {
+
+");
+}
@@ -0,0 +1,388 @@
+extern crate graphic_backend;
+
+use std::ops::Range;
+
+use graphic_backend::*;
+use graphic_backend::software::ShaderEnv;
+use graphic_backend::testing::headless_opengl;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec3 position;
+    void main() {
+        gl_Position = vec4(position, 1.0);
+    }
+"#;
+// `offset` comes first so that `position` is at another location than in
+// `VS_SRC`
+static VS_OFFSET_SRC: &'static str = r#"
+    #version 150
+    in vec2 offset;
+    in vec3 position;
+    void main() {
+        gl_Position = vec4(position.xy + offset, position.z, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    uniform vec4 color;
+    out vec4 out_color;
+    void main() {
+        out_color = color;
+    }
+"#;
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+fn software() -> Software {
+    let facade = Software::new(8, 8);
+    let color = |env: &ShaderEnv| match env.uniform("color") {
+        Some(&Uniform::Vec4(r, g, b, a)) => [r, g, b, a],
+        _ => [0.0; 4],
+    };
+    facade.shader(VS_SRC,
+                  FS_SRC,
+                  |attrs, _| {
+                      let p = attrs.vec3("position");
+                      ([p[0], p[1], p[2], 1.0], Vec::new())
+                  },
+                  move |_, env| color(env));
+    facade.shader(VS_OFFSET_SRC,
+                  FS_SRC,
+                  |attrs, _| {
+                      let (p, o) = (attrs.vec3("position"), attrs.vec2("offset"));
+                      ([p[0] + o[0], p[1] + o[1], p[2], 1.0], Vec::new())
+                  },
+                  move |_, env| color(env));
+    facade
+}
+
+/// Two triangles covering column `x` of the 8 of the frame.
+fn column(x: u32) -> Vec<f32> {
+    let (l, r) = (-1.0 + 0.25 * x as f32, -0.75 + 0.25 * x as f32);
+    vec![l, -1.0, 0.0, r, -1.0, 0.0, l, 1.0, 0.0, l, 1.0, 0.0, r, -1.0, 0.0, r, 1.0, 0.0]
+}
+
+/// Two triangles covering the whole frame at depth `z`.
+fn full(z: f32) -> Vec<f32> {
+    vec![-1.0, -1.0, z, 1.0, -1.0, z, -1.0, 1.0, z, -1.0, 1.0, z, 1.0, -1.0, z, 1.0, 1.0, z]
+}
+
+fn uniforms_with_color(color: [f32; 4]) -> Uniforms<u32> {
+    let mut uniforms = Uniforms::new();
+    uniforms.add_uniform("color", Uniform::Vec4(color[0], color[1], color[2], color[3]));
+    uniforms
+}
+
+fn draw<F: Facade>(facade: &F,
+                   frame: &mut BFrame,
+                   positions: Vec<f32>,
+                   color: [f32; 4],
+                   params: &DrawParameters) {
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position", InputBuffer::Vec3(positions))
+        .build()
+        .unwrap();
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms_with_color(color), params).unwrap();
+}
+
+/// The color at `(x, y)` of `frame`, rows counted from the top.
+fn pixel(frame: &BFrame, x: u32, y: u32) -> [f32; 4] {
+    let p = frame.read_pixels().get_pixel(x, y).data;
+    [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]
+}
+
+/// Asserts that `actual` is `expected` to within the rounding of 8 bit
+/// channels.
+fn assert_color(actual: [f32; 4], expected: [f32; 4]) {
+    assert!(actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() <= 1.5 / 255.0),
+            "{:?} is not {:?}",
+            actual,
+            expected);
+}
+
+/// The pixels `frame` is not black at, row by row from the top.
+fn lit(frame: &BFrame) -> Vec<(u32, u32)> {
+    frame.read_pixels()
+        .enumerate_pixels()
+        .filter(|&(_, _, p)| p.data[..3].iter().any(|&c| c > 0))
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+/// The pixels of `columns` of an 8 by 8 frame, in the order of `lit`.
+fn in_columns(columns: &[u32]) -> Vec<(u32, u32)> {
+    in_rect(0..8, 0..8).into_iter().filter(|&(x, _)| columns.contains(&x)).collect()
+}
+
+/// The pixels from columns `xs` and rows `ys`, rows counted from the top, in
+/// the order of `lit`.
+fn in_rect(xs: Range<u32>, ys: Range<u32>) -> Vec<(u32, u32)> {
+    ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+}
+
+/// Columns 0, 1 and 2 packed into one buffer, with an instance moving them 4
+/// columns to the right. Indexed buffers store them in reverse and put them
+/// back in order through the indices.
+fn packed_columns<F: Facade>(facade: &F, indexed: bool) -> BVertexBuffer {
+    let mut positions = Vec::new();
+    let order = if indexed { [2, 1, 0] } else { [0, 1, 2] };
+    for &x in order.iter() {
+        positions.extend(column(x));
+    }
+    let mut builder = facade.vertex_buffer()
+        .add_input("position", InputBuffer::Vec3(positions))
+        .add_instance_input("offset", InputBuffer::Vec2(vec![0.0, 0.0, 1.0, 0.0]), 1);
+    if indexed {
+        let indices = (12..18).chain(6..12).chain(0..6).collect();
+        builder = builder.indices(IndexBuffer::U16(indices));
+    }
+    builder.build().unwrap()
+}
+
+/// Draws ranges of `packed_columns` once and as two instances, which on
+/// OpenGL go through `MultiDraw*` and a loop of `Draw*Instanced` calls.
+fn ranges_and_instances<F: Facade>(facade: &F, indexed: bool) {
+    let program = facade.program(VS_OFFSET_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = packed_columns(facade, indexed);
+    let uniforms = uniforms_with_color(RED);
+    let params = DrawParameters::default();
+    let cases: [(&[Range<usize>], usize, &[u32]); 5] = [(&[6..12], 1, &[1]),
+                                                         (&[0..6, 12..18], 1, &[0, 2]),
+                                                         (&[6..18], 1, &[1, 2]),
+                                                         (&[0..18], 2, &[0, 1, 2, 4, 5, 6]),
+                                                         (&[0..6, 12..18], 2, &[0, 2, 4, 6])];
+    for &(ranges, instances, columns) in cases.iter() {
+        let mut frame = facade.frame();
+        frame.clear(Some(BLACK), None, None);
+        frame.multi_draw_instanced(&program,
+                                  DrawType::Triangles,
+                                  &vb,
+                                  ranges,
+                                  instances,
+                                  &uniforms,
+                                  &params)
+            .unwrap();
+        assert_eq!(lit(&frame),
+                   in_columns(columns),
+                   "{:?} drawn {} times",
+                   ranges,
+                   instances);
+        frame.finish().unwrap();
+    }
+}
+
+/// One mesh drawn by a program reading `offset` and one ignoring it, which
+/// link `position` to different locations, alternating between them.
+fn shared_vertex_buffer<F: Facade>(facade: &F) {
+    let offset = facade.program(VS_OFFSET_SRC, FS_SRC, None, "out_color").unwrap();
+    let plain = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("offset", InputBuffer::Vec2(vec![0.5, 0.0].repeat(6)))
+        .add_input("position", InputBuffer::Vec3(column(0)))
+        .build()
+        .unwrap();
+    let params = DrawParameters::default();
+    let mut frame = facade.frame();
+    frame.clear(Some(BLACK), None, None);
+    let draws = [(&offset, RED), (&plain, GREEN), (&offset, BLUE)];
+    for &(program, color) in draws.iter() {
+        frame.draw(program,
+                  DrawType::Triangles,
+                  &vb,
+                  &uniforms_with_color(color),
+                  &params)
+            .unwrap();
+    }
+    assert_eq!(lit(&frame), in_columns(&[0, 2]));
+    assert_color(pixel(&frame, 0, 4), GREEN);
+    assert_color(pixel(&frame, 2, 4), BLUE);
+    frame.finish().unwrap();
+}
+
+/// Draws and clears of parts of the buffers, all in one frame.
+fn clearing<F: Facade>(facade: &F) {
+    let less = DrawParameters { depth: Depth::less(), ..Default::default() };
+    let mut frame = facade.frame();
+    frame.clear(Some(BLACK), Some(1.0), None);
+    // every draw of a frame shows up
+    draw(facade, &mut frame, column(1), RED, &Default::default());
+    draw(facade, &mut frame, column(3), GREEN, &Default::default());
+    assert_eq!(lit(&frame), in_columns(&[1, 3]));
+    assert_color(pixel(&frame, 1, 0), RED);
+    assert_color(pixel(&frame, 3, 0), GREEN);
+
+    // clearing color ignores the scissor rectangle of the last draw
+    let scissor = Rect {
+        left: 0,
+        bottom: 0,
+        width: 1,
+        height: 1,
+    };
+    draw(facade,
+         &mut frame,
+         full(0.0),
+         BLUE,
+         &DrawParameters { scissor: Some(scissor), ..Default::default() });
+    frame.clear(Some(BLACK), None, None);
+    assert_eq!(lit(&frame), vec![]);
+
+    // clearing only depth keeps the colors, and only color keeps the depths
+    draw(facade, &mut frame, full(0.0), RED, &less);
+    frame.clear(None, Some(1.0), None);
+    assert_color(pixel(&frame, 4, 4), RED);
+    draw(facade, &mut frame, full(0.5), GREEN, &less);
+    assert_color(pixel(&frame, 4, 4), GREEN);
+    frame.clear(Some(BLUE), None, None);
+    draw(facade, &mut frame, full(0.75), RED, &less);
+    assert_color(pixel(&frame, 4, 4), BLUE);
+    frame.finish().unwrap();
+}
+
+fn viewport_and_scissor<F: Facade>(facade: &F) {
+    let mut frame = facade.frame();
+    assert_eq!(frame.dimensions(), (8, 8));
+
+    let scissor = Rect {
+        left: 2,
+        bottom: 1,
+        width: 3,
+        height: 2,
+    };
+    frame.clear(Some(BLACK), None, None);
+    draw(facade,
+         &mut frame,
+         full(0.0),
+         RED,
+         &DrawParameters { scissor: Some(scissor), ..Default::default() });
+    assert_eq!(lit(&frame), in_rect(2..5, 5..7));
+
+    // the viewport maps the whole of clip space to its rectangle
+    let viewport = Rect {
+        left: 4,
+        bottom: 0,
+        width: 4,
+        height: 4,
+    };
+    frame.clear(Some(BLACK), None, None);
+    draw(facade,
+         &mut frame,
+         full(0.0),
+         RED,
+         &DrawParameters { viewport: Some(viewport), ..Default::default() });
+    assert_eq!(lit(&frame), in_rect(4..8, 4..8));
+    frame.clear(Some(BLACK), None, None);
+    let left_half = vec![-1.0, -1.0, 0.0, 0.0, -1.0, 0.0, -1.0, 1.0, 0.0, -1.0, 1.0, 0.0, 0.0,
+                         -1.0, 0.0, 0.0, 1.0, 0.0];
+    draw(facade,
+         &mut frame,
+         left_half,
+         RED,
+         &DrawParameters { viewport: Some(viewport), ..Default::default() });
+    assert_eq!(lit(&frame), in_rect(4..6, 4..8));
+    frame.finish().unwrap();
+
+    // without a viewport draws cover whatever size of frame they go to
+    let texture = facade.texture2d(ColorFormat::RGBA, 4, 2, vec![0; 4 * 2 * 4]).unwrap();
+    let framebuffer = facade.framebuffer(&[&texture], DepthStencil::None).unwrap();
+    let mut offscreen = framebuffer.frame();
+    assert_eq!(offscreen.dimensions(), (4, 2));
+    draw(facade, &mut offscreen, full(0.0), RED, &Default::default());
+    assert_eq!(lit(&offscreen), in_rect(0..4, 0..2));
+    offscreen.finish().unwrap();
+    let mut frame = facade.frame();
+    frame.clear(Some(BLACK), None, None);
+    draw(facade, &mut frame, full(0.0), RED, &Default::default());
+    assert_eq!(lit(&frame), in_rect(0..8, 0..8));
+    frame.finish().unwrap();
+}
+
+#[test]
+fn ranges_and_instances_software() {
+    ranges_and_instances(&software(), false);
+}
+
+#[test]
+fn indexed_ranges_and_instances_software() {
+    ranges_and_instances(&software(), true);
+}
+
+#[test]
+fn shared_vertex_buffer_software() {
+    shared_vertex_buffer(&software());
+}
+
+#[test]
+fn clearing_software() {
+    clearing(&software());
+}
+
+#[test]
+fn viewport_and_scissor_software() {
+    viewport_and_scissor(&software());
+}
+
+#[test]
+fn ranges_and_instances_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        ranges_and_instances(&facade, false);
+    }
+}
+
+#[test]
+fn indexed_ranges_and_instances_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        ranges_and_instances(&facade, true);
+    }
+}
+
+#[test]
+fn shared_vertex_buffer_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        let locations = |program: &BProgram| {
+            program.attributes().iter().find(|a| a.name == "position").unwrap().location
+        };
+        let offset = facade.program(VS_OFFSET_SRC, FS_SRC, None, "out_color").unwrap();
+        let plain = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+        // otherwise one vertex array would do for both programs
+        assert!(locations(&offset) != locations(&plain));
+        shared_vertex_buffer(&facade);
+    }
+}
+
+#[test]
+fn clearing_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        clearing(&facade);
+    }
+}
+
+#[test]
+fn viewport_and_scissor_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        viewport_and_scissor(&facade);
+    }
+}
+
+#[test]
+fn resize_opengl_headless() {
+    let facade = match headless_opengl(8, 8) {
+        Some(facade) => facade,
+        None => return,
+    };
+    for &(width, height) in [(4, 6), (8, 8)].iter() {
+        facade.resize(width, height);
+        let mut frame = facade.frame();
+        assert_eq!(frame.dimensions(), (width, height));
+        frame.clear(Some(BLACK), Some(1.0), None);
+        draw(&facade, &mut frame, full(0.0), RED, &Default::default());
+        let pixels = frame.read_pixels();
+        assert_eq!(pixels.dimensions(), (width, height));
+        assert_eq!(lit(&frame), in_rect(0..width, 0..height));
+        frame.finish().unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+#[macro_use]
+extern crate graphic_backend;
+extern crate image;
+
+use std::path::Path;
+use image::GenericImage;
+
+use graphic_backend::*;
+use graphic_backend::testing::*;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec3 position;
+    in vec2 coord;
+    out vec2 coord0;
+    void main() {
+        coord0 = coord;
+        gl_Position = vec4(position, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    in vec2 coord0;
+    out vec4 out_color;
+    uniform sampler2D tex;
+    void main() {
+        out_color = texture(tex, coord0);
+    }
+"#;
+
+static FS_TWO_TARGETS_SRC: &'static str = r#"
+    #version 150
+    in vec2 coord0;
+    out vec4 out_colors[2];
+    uniform sampler2D tex;
+    void main() {
+        vec4 color = texture(tex, coord0);
+        out_colors[0] = color;
+        out_colors[1] = color.bgra;
+    }
+"#;
+
+fn software() -> Software {
+    let facade = Software::new(320, 240);
+    facade.shader(VS_SRC,
+                  FS_SRC,
+                  |attrs, _| {
+                      let p = attrs.vec3("position");
+                      ([p[0], p[1], p[2], 1.0], attrs.vec2("coord").to_vec())
+                  },
+                  |coord0, env| env.texture("tex", coord0[0], coord0[1]));
+    facade.shader(VS_SRC,
+                  FS_TWO_TARGETS_SRC,
+                  |attrs, _| {
+                      let p = attrs.vec3("position");
+                      ([p[0], p[1], p[2], 1.0], attrs.vec2("coord").to_vec())
+                  },
+                  |coord0, env| {
+                      let c = env.texture("tex", coord0[0], coord0[1]);
+                      [c, [c[2], c[1], c[0], c[3]]]
+                  });
+    facade
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: [f32; 3],
+    coord: [f32; 2],
+}
+
+impl_shader_inputs!(Vertex, position, coord);
+
+/// The scene of `examples/triangle.rs`.
+fn textured_triangle<F: Facade>(facade: &F, frame: &mut BFrame) {
+    use graphic_backend::InputBuffer::*;
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position",
+                   Vec3(vec![-1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0]))
+        .add_input("coord", Vec2(vec![-1.0, -1.0, 0.0, 1.0, 1.0, -1.0]))
+        .build()
+        .unwrap();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw_textured(facade, frame, &program, &vb);
+}
+
+/// `textured_triangle` with the attributes interleaved in one buffer.
+fn interleaved_triangle<F: Facade>(facade: &F, frame: &mut BFrame) {
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vertices = [Vertex {
+                        position: [-1.0, -1.0, 0.0],
+                        coord: [-1.0, -1.0],
+                    },
+                    Vertex {
+                        position: [0.0, 1.0, 0.0],
+                        coord: [0.0, 1.0],
+                    },
+                    Vertex {
+                        position: [1.0, -1.0, 0.0],
+                        coord: [1.0, -1.0],
+                    }];
+    let vb = facade.vertex_buffer().add_vertices(&vertices).build().unwrap();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw_textured(facade, frame, &program, &vb);
+}
+
+/// `interleaved_triangle` with the vertices stored out of order and put
+/// back in order by an index buffer.
+fn indexed_triangle<F: Facade>(facade: &F, frame: &mut BFrame) {
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vertices = [Vertex {
+                        position: [1.0, -1.0, 0.0],
+                        coord: [1.0, -1.0],
+                    },
+                    Vertex {
+                        position: [-1.0, -1.0, 0.0],
+                        coord: [-1.0, -1.0],
+                    },
+                    Vertex {
+                        position: [0.0, 1.0, 0.0],
+                        coord: [0.0, 1.0],
+                    }];
+    let vb = facade.vertex_buffer()
+        .add_vertices(&vertices)
+        .indices(IndexBuffer::U16(vec![1, 2, 0]))
+        .build()
+        .unwrap();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw_textured(facade, frame, &program, &vb);
+}
+
+/// `textured_triangle` with its data uploaded after the buffers were built.
+fn updated_triangle<F: Facade>(facade: &F, frame: &mut BFrame) {
+    use graphic_backend::InputBuffer::*;
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let mut vb = facade.vertex_buffer()
+        .usage(BufferUsage::Stream)
+        .add_input("position", Vec3(vec![0.0; 6]))
+        .add_input("coord", Vec2(vec![0.0; 2]))
+        .build()
+        .unwrap();
+    let positions = [-1.0f32, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0];
+    vb.buffer_mut(0).set_data(slice_as_bytes(&positions)).unwrap();
+    vb.buffer_mut(1).resize(3).unwrap();
+    let coords = [-1.0f32, -1.0, 0.0, 1.0, 1.0, -1.0];
+    vb.buffer_mut(1).write(0, slice_as_bytes(&coords)).unwrap();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw_textured(facade, frame, &program, &vb);
+}
+
+/// `textured_triangle` rendered into a texture, which a second pass then
+/// copies to the screen.
+fn render_to_texture<F: Facade>(facade: &F, frame: &mut BFrame) {
+    let color = facade.texture2d(ColorFormat::RGBA, 320, 240, vec![0; 320 * 240 * 4]).unwrap();
+    {
+        let framebuffer = facade.framebuffer(&[&color], DepthStencil::Depth).unwrap();
+        let mut offscreen = framebuffer.frame();
+        textured_triangle(facade, &mut offscreen);
+        offscreen.finish().unwrap();
+    }
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw_texture(facade, frame, &color);
+}
+
+/// Draws `texture` over the whole of `frame`.
+fn draw_texture<F: Facade>(facade: &F, frame: &mut BFrame, texture: &BTexture2D) {
+    use graphic_backend::InputBuffer::*;
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let quad = facade.vertex_buffer()
+        .add_input("position",
+                   Vec3(vec![-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, -1.0, 1.0, 0.0, 1.0, 1.0, 0.0]))
+        .add_input("coord", Vec2(vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0]))
+        .build()
+        .unwrap();
+    let uniforms = uniforms! {
+        tex: texture.as_uniform()
+    };
+    frame.draw(&program, DrawType::TriangleStrip, &quad, &uniforms, &Default::default())
+        .unwrap();
+}
+
+/// `textured_triangle` with a back-facing decoy in front of it, culled, and
+/// a front-facing one behind it, failing the depth test.
+fn depth_tested_triangle<F: Facade>(facade: &F, frame: &mut BFrame) {
+    use graphic_backend::InputBuffer::*;
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position",
+                   Vec3(vec![1.0, -1.0, -0.5, 0.0, 1.0, -0.5, -1.0, -1.0, -0.5,
+                             -1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0,
+                             -1.0, -1.0, 0.5, 0.0, 1.0, 0.5, 1.0, -1.0, 0.5]))
+        .add_input("coord",
+                   Vec2(vec![0.5, 0.5, 0.5, 0.5, 0.5, 0.5,
+                             -1.0, -1.0, 0.0, 1.0, 1.0, -1.0,
+                             0.5, 0.5, 0.5, 0.5, 0.5, 0.5]))
+        .build()
+        .unwrap();
+    let tex = denim(facade);
+    let uniforms = uniforms! {
+        tex: tex.as_uniform()
+    };
+    let params = DrawParameters {
+        depth: Depth::less(),
+        cull: Cull::Back,
+        front_face: Winding::Clockwise,
+        ..Default::default()
+    };
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &params).unwrap();
+}
+
+fn draw_textured<F: Facade>(facade: &F,
+                            frame: &mut BFrame,
+                            program: &BProgram,
+                            vb: &BVertexBuffer) {
+    let tex = denim(facade);
+    let uniforms = uniforms! {
+        tex: tex.as_uniform()
+    };
+    frame.draw(program, DrawType::Triangles, vb, &uniforms, &Default::default()).unwrap();
+}
+
+fn denim<F: Facade>(facade: &F) -> BTexture2D {
+    let img = image::open(&Path::new("resource/denim.png")).unwrap();
+    let (width, height) = img.dimensions();
+    facade.texture2d(ColorFormat::RGBA, width, height, img.to_rgba().into_raw()).unwrap()
+}
+
+#[test]
+fn triangle_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                textured_triangle)
+        .unwrap();
+}
+
+#[test]
+fn interleaved_triangle_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                interleaved_triangle)
+        .unwrap();
+}
+
+#[test]
+fn indexed_triangle_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                indexed_triangle)
+        .unwrap();
+}
+
+#[test]
+fn updated_triangle_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                updated_triangle)
+        .unwrap();
+}
+
+#[test]
+fn render_to_texture_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                render_to_texture)
+        .unwrap();
+}
+
+#[test]
+fn depth_tested_triangle_software() {
+    check_scene(&software(),
+                "tests/reference/triangle.png",
+                &Tolerance::default(),
+                depth_tested_triangle)
+        .unwrap();
+}
+
+/// Checks `scene` on a headless OpenGL context, if `OPENGL_VAR` is set.
+/// GPU drivers filter slightly differently than llvmpipe, which rendered the
+/// references, hence the tolerance.
+fn check_opengl<S>(reference: &str, scene: S)
+    where S: FnOnce(&OpenGL, &mut BFrame)
+{
+    let facade = match headless_opengl(320, 240) {
+        Some(facade) => facade,
+        None => return,
+    };
+    let tolerance = Tolerance {
+        channel: 8,
+        max_differing_pixels: 320,
+    };
+    check_scene(&facade, reference, &tolerance, scene).unwrap();
+}
+
+#[test]
+fn triangle_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", textured_triangle);
+}
+
+#[test]
+fn interleaved_triangle_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", interleaved_triangle);
+}
+
+#[test]
+fn indexed_triangle_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", indexed_triangle);
+}
+
+#[test]
+fn updated_triangle_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", updated_triangle);
+}
+
+#[test]
+fn render_to_texture_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", render_to_texture);
+}
+
+/// Draws `textured_triangle` into two targets, the second getting the colors
+/// of the first with red and blue swapped.
+fn multiple_render_targets<F: Facade>(facade: &F) {
+    use graphic_backend::InputBuffer::*;
+
+    let empty = || facade.texture2d(ColorFormat::RGBA, 320, 240, vec![0; 320 * 240 * 4]).unwrap();
+    let (first, second) = (empty(), empty());
+    let program = facade.program(VS_SRC, FS_TWO_TARGETS_SRC, None, "out_colors").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position",
+                   Vec3(vec![-1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0]))
+        .add_input("coord", Vec2(vec![-1.0, -1.0, 0.0, 1.0, 1.0, -1.0]))
+        .build()
+        .unwrap();
+    let framebuffer = facade.framebuffer(&[&first, &second], DepthStencil::None).unwrap();
+    let mut offscreen = framebuffer.frame();
+    offscreen.clear(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+    draw_textured(facade, &mut offscreen, &program, &vb);
+    let first_pixels = offscreen.read_pixels();
+    offscreen.finish().unwrap();
+    let tolerance = Tolerance {
+        channel: 8,
+        max_differing_pixels: 320,
+    };
+    check_image(&first_pixels, "tests/reference/triangle.png", &tolerance).unwrap();
+
+    let mut frame = facade.frame();
+    draw_texture(facade, &mut frame, &second);
+    let second_pixels = frame.read_pixels();
+    frame.finish().unwrap();
+    for (a, b) in first_pixels.pixels().zip(second_pixels.pixels()) {
+        let swapped = [a.data[2], a.data[1], a.data[0], a.data[3]];
+        assert!(swapped.iter().zip(b.data.iter()).all(|(&s, &b)| (s as i16 - b as i16).abs() <= 2),
+                "{:?} is not {:?} swapped",
+                b.data,
+                a.data);
+    }
+}
+
+#[test]
+fn multiple_render_targets_software() {
+    multiple_render_targets(&software());
+}
+
+#[test]
+fn multiple_render_targets_opengl_headless() {
+    if let Some(facade) = headless_opengl(320, 240) {
+        multiple_render_targets(&facade);
+    }
+}
+
+#[test]
+fn depth_tested_triangle_opengl_headless() {
+    check_opengl("tests/reference/triangle.png", depth_tested_triangle);
+}
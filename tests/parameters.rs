@@ -0,0 +1,365 @@
+extern crate graphic_backend;
+
+use graphic_backend::*;
+use graphic_backend::testing::headless_opengl;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec3 position;
+    void main() {
+        gl_Position = vec4(position, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    uniform vec4 color;
+    out vec4 out_color;
+    void main() {
+        out_color = color;
+    }
+"#;
+
+fn software() -> Software {
+    let facade = Software::new(8, 8);
+    facade.shader(VS_SRC,
+                  FS_SRC,
+                  |attrs, _| {
+                      let p = attrs.vec3("position");
+                      ([p[0], p[1], p[2], 1.0], Vec::new())
+                  },
+                  |_, env| match env.uniform("color") {
+                      Some(&Uniform::Vec4(r, g, b, a)) => [r, g, b, a],
+                      _ => [0.0; 4],
+                  });
+    facade
+}
+
+/// The centre of pixel `i` of the 8 along an axis, in clip space.
+fn center(i: u32) -> f32 {
+    -0.875 + 0.25 * i as f32
+}
+
+/// Two triangles covering columns `left` to `right` of the frame, at depth
+/// `z`.
+fn columns(left: u32, right: u32, z: f32) -> Vec<f32> {
+    let (l, r) = (-1.0 + 0.25 * left as f32, -1.0 + 0.25 * (right + 1) as f32);
+    vec![l, -1.0, z, r, -1.0, z, l, 1.0, z, l, 1.0, z, r, -1.0, z, r, 1.0, z]
+}
+
+fn full(z: f32) -> Vec<f32> {
+    columns(0, 7, z)
+}
+
+fn draw<F: Facade>(facade: &F,
+                   frame: &mut BFrame,
+                   positions: Vec<f32>,
+                   color: [f32; 4],
+                   params: &DrawParameters) {
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position", InputBuffer::Vec3(positions))
+        .build()
+        .unwrap();
+    let uniforms = uniforms_with_color(color);
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms, params).unwrap();
+}
+
+fn uniforms_with_color(color: [f32; 4]) -> Uniforms<u32> {
+    let mut uniforms = Uniforms::new();
+    uniforms.add_uniform("color", Uniform::Vec4(color[0], color[1], color[2], color[3]));
+    uniforms
+}
+
+/// The color at `(x, y)` of `frame`, rows counted from the top.
+fn pixel(frame: &BFrame, x: u32, y: u32) -> [f32; 4] {
+    let p = frame.read_pixels().get_pixel(x, y).data;
+    [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]
+}
+
+/// Asserts that `actual` is `expected` to within the rounding of 8 bit
+/// channels.
+fn assert_color(actual: [f32; 4], expected: [f32; 4]) {
+    assert!(actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() <= 1.5 / 255.0),
+            "{:?} is not {:?}",
+            actual,
+            expected);
+}
+
+/// The pixels `frame` is not black at.
+fn lit(frame: &BFrame) -> Vec<(u32, u32)> {
+    frame.read_pixels()
+        .enumerate_pixels()
+        .filter(|&(_, _, p)| p.data[..3].iter().any(|&c| c > 0))
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+const DESTINATION: [f32; 4] = [0.2, 0.4, 0.6, 0.8];
+const SOURCE: [f32; 4] = [0.6, 0.2, 1.0, 0.4];
+
+fn blending<F: Facade>(facade: &F) {
+    let equation = |op, source, destination| {
+        BlendEquation {
+            op: op,
+            source: source,
+            destination: destination,
+        }
+    };
+    let blend = |color, alpha| {
+        Blend {
+            color: color,
+            alpha: alpha,
+            constant: [0.25, 0.5, 0.75, 1.0],
+        }
+    };
+    let replace = equation(BlendOp::Add, BlendFactor::One, BlendFactor::Zero);
+    let ones = |op| equation(op, BlendFactor::One, BlendFactor::One);
+    let cases = [// the alpha equation applies to alpha alone
+                 (blend(Blend::alpha().color, replace), [0.36, 0.32, 0.76, 0.4]),
+                 (blend(ones(BlendOp::Subtract), ones(BlendOp::Subtract)), [0.4, 0.0, 0.4, 0.0]),
+                 (blend(ones(BlendOp::ReverseSubtract), ones(BlendOp::ReverseSubtract)),
+                  [0.0, 0.2, 0.0, 0.4]),
+                 // min and max ignore the factors
+                 (blend(ones(BlendOp::Min), ones(BlendOp::Min)), [0.2, 0.2, 0.6, 0.4]),
+                 (blend(ones(BlendOp::Max), ones(BlendOp::Max)), [0.6, 0.4, 1.0, 0.8]),
+                 (blend(equation(BlendOp::Add,
+                                 BlendFactor::ConstantColor,
+                                 BlendFactor::OneMinusConstantColor),
+                        replace),
+                  [0.3, 0.3, 0.9, 0.4]),
+                 (blend(equation(BlendOp::Add, BlendFactor::DestinationColor, BlendFactor::Zero),
+                        equation(BlendOp::Add,
+                                 BlendFactor::OneMinusDestinationAlpha,
+                                 BlendFactor::DestinationAlpha)),
+                  [0.12, 0.08, 0.6, 0.72])];
+    for &(blend, expected) in cases.iter() {
+        let mut frame = facade.frame();
+        frame.clear(Some(DESTINATION), None, None);
+        let params = DrawParameters { blend: Some(blend), ..Default::default() };
+        draw(facade, &mut frame, full(0.0), SOURCE, &params);
+        assert_color(pixel(&frame, 4, 4), expected);
+        frame.finish().unwrap();
+    }
+}
+
+#[test]
+fn blending_software() {
+    blending(&software());
+}
+
+#[test]
+fn blending_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        blending(&facade);
+    }
+}
+
+fn stencil<F: Facade>(facade: &F) {
+    let stencil = |test, reference, pass| {
+        Stencil {
+            test: test,
+            reference: reference,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: pass,
+        }
+    };
+    let params = |stencil| DrawParameters { stencil: Some(stencil), ..Default::default() };
+    let (red, green, blue) = ([1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]);
+    let mut frame = facade.frame();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), None, Some(0));
+
+    // 1 on the left half, 0 on the right
+    let replace = params(stencil(Comparison::Always, 1, StencilOp::Replace));
+    draw(facade, &mut frame, columns(0, 3, 0.0), red, &replace);
+    // 2 and 1
+    let increment = params(stencil(Comparison::Always, 0, StencilOp::Increment));
+    draw(facade, &mut frame, full(0.0), red, &increment);
+    // the right half fails and is inverted to 254
+    let invert = params(Stencil {
+        fail: StencilOp::Invert,
+        ..stencil(Comparison::Equal, 2, StencilOp::Keep)
+    });
+    draw(facade, &mut frame, full(0.0), green, &invert);
+    assert_color(pixel(&frame, 1, 4), green);
+    assert_color(pixel(&frame, 6, 4), red);
+    let equal = params(stencil(Comparison::Equal, 254, StencilOp::Keep));
+    draw(facade, &mut frame, full(0.0), blue, &equal);
+    assert_color(pixel(&frame, 1, 4), green);
+    assert_color(pixel(&frame, 6, 4), blue);
+
+    // only the low bits are replaced: 0x0f on the left, 0xff on the right
+    let masked = params(Stencil {
+        write_mask: 0x0f,
+        ..stencil(Comparison::Always, 0xff, StencilOp::Replace)
+    });
+    draw(facade, &mut frame, full(0.0), blue, &masked);
+    let white = [1.0; 4];
+    let low = params(stencil(Comparison::Equal, 0x0f, StencilOp::Keep));
+    draw(facade, &mut frame, full(0.0), white, &low);
+    assert_color(pixel(&frame, 1, 4), white);
+    assert_color(pixel(&frame, 6, 4), blue);
+    // the read mask applies to both the reference and the stored value
+    let high = params(Stencil {
+        read_mask: 0xf0,
+        ..stencil(Comparison::Equal, 0xf3, StencilOp::Keep)
+    });
+    draw(facade, &mut frame, full(0.0), red, &high);
+    assert_color(pixel(&frame, 1, 4), white);
+    assert_color(pixel(&frame, 6, 4), red);
+    frame.finish().unwrap();
+}
+
+#[test]
+fn stencil_software() {
+    stencil(&software());
+}
+
+#[test]
+fn stencil_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        stencil(&facade);
+    }
+}
+
+fn color_mask<F: Facade>(facade: &F) {
+    let mut frame = facade.frame();
+    frame.clear(Some(DESTINATION), None, None);
+    let params = DrawParameters { color_mask: [true, false, true, false], ..Default::default() };
+    draw(facade, &mut frame, full(0.0), [1.0; 4], &params);
+    assert_color(pixel(&frame, 4, 4), [1.0, 0.4, 1.0, 0.8]);
+    frame.finish().unwrap();
+}
+
+#[test]
+fn color_mask_software() {
+    color_mask(&software());
+}
+
+#[test]
+fn color_mask_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        color_mask(&facade);
+    }
+}
+
+fn polygon_offset<F: Facade>(facade: &F) {
+    let (red, green, blue) = ([1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]);
+    let depth = DrawParameters { depth: Depth::less(), ..Default::default() };
+    let offset = |units| {
+        DrawParameters {
+            polygon_offset: Some(PolygonOffset {
+                factor: 0.0,
+                units: units,
+            }),
+            ..depth
+        }
+    };
+    let mut frame = facade.frame();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    draw(facade, &mut frame, full(0.0), red, &depth);
+    // the same depth fails the test unless pulled closer
+    draw(facade, &mut frame, full(0.0), blue, &depth);
+    assert_color(pixel(&frame, 4, 4), red);
+    draw(facade, &mut frame, full(0.0), green, &offset(-4.0));
+    assert_color(pixel(&frame, 4, 4), green);
+    draw(facade, &mut frame, full(0.0), blue, &offset(4.0));
+    assert_color(pixel(&frame, 4, 4), green);
+    frame.finish().unwrap();
+}
+
+#[test]
+fn polygon_offset_software() {
+    polygon_offset(&software());
+}
+
+#[test]
+fn polygon_offset_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        polygon_offset(&facade);
+    }
+}
+
+/// A triangle with its corners on the centres of pixels (1, 6), (6, 6)
+/// and (1, 1), rows counted from the top.
+fn corner_triangle() -> Vec<f32> {
+    vec![center(1), center(1), 0.0, center(6), center(1), 0.0, center(1), center(6), 0.0]
+}
+
+fn polygon_modes<F: Facade>(facade: &F) {
+    let white = [1.0; 4];
+    let mut frame = facade.frame();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+    let line = DrawParameters { polygon_mode: PolygonMode::Line, ..Default::default() };
+    draw(facade, &mut frame, corner_triangle(), white, &line);
+    let edges = lit(&frame);
+    // the middle of each edge but not the inside
+    for p in [(3, 6), (1, 3), (4, 4)].iter() {
+        assert!(edges.contains(p), "{:?} is not drawn", p);
+    }
+    assert!(!edges.contains(&(2, 5)));
+
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+    let point = DrawParameters { polygon_mode: PolygonMode::Point, ..Default::default() };
+    draw(facade, &mut frame, corner_triangle(), white, &point);
+    assert_eq!(lit(&frame), vec![(1, 1), (1, 6), (6, 6)]);
+    frame.finish().unwrap();
+}
+
+#[test]
+fn polygon_modes_software() {
+    polygon_modes(&software());
+}
+
+#[test]
+fn polygon_modes_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        polygon_modes(&facade);
+    }
+}
+
+fn culling<F: Facade>(facade: &F) {
+    // counterclockwise on the left half, clockwise on the right
+    let left = columns(0, 3, 0.0)[..9].to_vec();
+    let mut right = columns(4, 7, 0.0)[..9].to_vec();
+    for i in 0..3 {
+        right.swap(3 + i, 6 + i);
+    }
+    let both: Vec<f32> = left.iter().chain(right.iter()).cloned().collect();
+    let cases = [(Cull::None, Winding::CounterClockwise, (true, true)),
+                 (Cull::Back, Winding::CounterClockwise, (true, false)),
+                 (Cull::Front, Winding::CounterClockwise, (false, true)),
+                 (Cull::Back, Winding::Clockwise, (false, true)),
+                 (Cull::FrontAndBack, Winding::CounterClockwise, (false, false))];
+    for &(cull, front_face, expected) in cases.iter() {
+        let mut frame = facade.frame();
+        frame.clear(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+        let params = DrawParameters {
+            cull: cull,
+            front_face: front_face,
+            ..Default::default()
+        };
+        draw(facade, &mut frame, both.clone(), [1.0; 4], &params);
+        let drawn = lit(&frame);
+        assert_eq!((drawn.contains(&(0, 7)), drawn.contains(&(7, 7))),
+                   expected,
+                   "{:?} culling with {:?} front faces",
+                   cull,
+                   front_face);
+        frame.finish().unwrap();
+    }
+}
+
+#[test]
+fn culling_software() {
+    culling(&software());
+}
+
+#[test]
+fn culling_opengl_headless() {
+    if let Some(facade) = headless_opengl(8, 8) {
+        culling(&facade);
+    }
+}
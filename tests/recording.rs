@@ -0,0 +1,244 @@
+#[macro_use]
+extern crate graphic_backend;
+
+use graphic_backend::*;
+use graphic_backend::recording::Command;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec2 position;
+    void main() {
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    uniform float brightness;
+    out vec4 out_color;
+    void main() {
+        out_color = vec4(brightness);
+    }
+"#;
+
+fn triangle(facade: &Recorder) -> (BProgram, BVertexBuffer) {
+    use graphic_backend::InputBuffer::*;
+
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position", Vec2(vec![-1.0, -1.0, 0.0, 1.0, 1.0, -1.0]))
+        .build()
+        .unwrap();
+    (program, vb)
+}
+
+#[test]
+fn frame_is_recorded_in_order() {
+    let facade = Recorder::new(320, 240);
+    let (program, vb) = triangle(&facade);
+    let uniforms = uniforms! {
+        brightness: Uniform::Float(0.5)
+    };
+    let params = DrawParameters { depth: Depth::less(), ..Default::default() };
+    let mut frame = facade.frame();
+    frame.clear(Some([0.0, 0.0, 0.0, 1.0]), Some(1.0), None);
+    frame.line_width(2.0);
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &params).unwrap();
+    frame.finish().unwrap();
+
+    let buffer = vb.get_buffers()[0].get_bind();
+    let vertex_buffer = vb.vertex_array(&*program).unwrap();
+    assert_eq!(facade.commands(),
+               vec![Command::CreateProgram {
+                        program: program.get_bind(),
+                        vssrc: String::from(VS_SRC),
+                        fssrc: String::from(FS_SRC),
+                        gssrc: None,
+                        out: String::from("out_color"),
+                    },
+                    Command::CreateVertexBuffer {
+                        vertex_buffer: vertex_buffer,
+                        buffers: vec![(buffer, BufferUsage::Static)],
+                        inputs: vec![(String::from("position"), 3, 0)],
+                        indices: None,
+                        primitive_restart: false,
+                    },
+                    Command::Clear {
+                        color: Some([0.0, 0.0, 0.0, 1.0]),
+                        depth: Some(1.0),
+                        stencil: None,
+                    },
+                    Command::LineWidth(2.0),
+                    Command::Draw {
+                        program: program.get_bind(),
+                        draw_type: DrawType::Triangles,
+                        vertex_buffer: vertex_buffer,
+                        ranges: vec![0..3],
+                        instances: 1,
+                        uniforms: uniforms.clone(),
+                        params: params,
+                    },
+                    Command::Finish]);
+}
+
+#[test]
+fn take_commands_starts_a_new_recording() {
+    let facade = Recorder::new(320, 240);
+    let (program, vb) = triangle(&facade);
+    assert_eq!(facade.take_commands().len(), 2);
+    assert_eq!(facade.commands(), vec![]);
+    let vertex_buffer = vb.vertex_array(&*program).unwrap();
+
+    let uniforms = uniforms! {
+        brightness: Uniform::Float(1.0)
+    };
+    let mut frame = facade.frame();
+    frame.draw_range(&program,
+                    DrawType::Triangles,
+                    &vb,
+                    1..3,
+                    &uniforms,
+                    &Default::default())
+        .unwrap();
+    frame.finish().unwrap();
+    assert_eq!(facade.take_commands(),
+               vec![Command::Draw {
+                        program: program.get_bind(),
+                        draw_type: DrawType::Triangles,
+                        vertex_buffer: vertex_buffer,
+                        ranges: vec![1..3],
+                        instances: 1,
+                        uniforms: uniforms.clone(),
+                        params: Default::default(),
+                    },
+                    Command::Finish]);
+    assert_eq!(facade.take_commands(), vec![]);
+}
+
+#[test]
+fn framebuffer_frames_bind_their_target() {
+    let facade = Recorder::new(320, 240);
+    let (program, vb) = triangle(&facade);
+    let color = facade.texture2d(ColorFormat::RGBA, 4, 4, vec![0; 4 * 4 * 4]).unwrap();
+    let framebuffer = facade.framebuffer(&[&color], DepthStencil::None).unwrap();
+    facade.take_commands();
+
+    let uniforms = uniforms! {
+        brightness: Uniform::Float(1.0)
+    };
+    let mut offscreen = framebuffer.frame();
+    offscreen.clear(Some([0.0; 4]), None, None);
+    offscreen.draw(&program, DrawType::Triangles, &vb, &uniforms, &Default::default()).unwrap();
+    offscreen.finish().unwrap();
+    let frame = facade.frame();
+    frame.finish().unwrap();
+
+    let commands = facade.take_commands();
+    assert_eq!(commands[0], Command::BindFramebuffer(framebuffer.get_bind()));
+    assert_eq!(commands[1],
+               Command::Clear {
+                   color: Some([0.0; 4]),
+                   depth: None,
+                   stencil: None,
+               });
+    match commands[2] {
+        Command::Draw { vertex_buffer, .. } => {
+            assert_eq!(vertex_buffer, vb.vertex_array(&*program).unwrap())
+        }
+        ref c => panic!("expected a draw, recorded {:?}", c),
+    }
+    // finishing an off-screen frame presents nothing
+    assert_eq!(commands[3..].to_vec(),
+               vec![Command::BindFramebuffer(0), Command::Finish]);
+}
+
+#[test]
+fn rejected_draws_are_not_recorded() {
+    let facade = Recorder::new(320, 240);
+    let (program, vb) = triangle(&facade);
+    facade.take_commands();
+
+    let mut frame = facade.frame();
+    let mistyped = uniforms! {
+        brightness: Uniform::Vec2(1.0, 1.0)
+    };
+    let wrong_type = frame.draw(&program,
+                                DrawType::Triangles,
+                                &vb,
+                                &mistyped,
+                                &Default::default());
+    match wrong_type {
+        Err(BackendError::InvalidUniformType { .. }) => (),
+        r => panic!("expected an invalid uniform type, got {:?}", r),
+    }
+    let uniforms = uniforms! {
+        brightness: Uniform::Float(1.0)
+    };
+    let out_of_range = frame.draw_range(&program,
+                                        DrawType::Triangles,
+                                        &vb,
+                                        0..4,
+                                        &uniforms,
+                                        &Default::default());
+    match out_of_range {
+        Err(BackendError::InvalidRange { .. }) => (),
+        r => panic!("expected an invalid range, got {:?}", r),
+    }
+    assert_eq!(facade.commands(), vec![]);
+}
+
+#[test]
+fn instanced_multi_draws_keep_their_ranges_and_rectangles() {
+    let facade = Recorder::new(320, 240);
+    let (program, vb) = triangle(&facade);
+    facade.take_commands();
+
+    let uniforms = uniforms! {
+        brightness: Uniform::Float(1.0)
+    };
+    let half = Rect {
+        left: 160,
+        bottom: 0,
+        width: 160,
+        height: 240,
+    };
+    let params = DrawParameters {
+        viewport: Some(half),
+        scissor: Some(Rect { height: 120, ..half }),
+        ..Default::default()
+    };
+    let mut frame = facade.frame();
+    assert_eq!(frame.dimensions(), (320, 240));
+    frame.multi_draw_instanced(&program,
+                              DrawType::Triangles,
+                              &vb,
+                              &[0..3, 1..3],
+                              4,
+                              &uniforms,
+                              &params)
+        .unwrap();
+    frame.clear(None, Some(1.0), None);
+    frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &Default::default()).unwrap();
+    frame.finish().unwrap();
+
+    let vertex_buffer = vb.vertex_array(&*program).unwrap();
+    let draw = |ranges, instances, params| {
+        Command::Draw {
+            program: program.get_bind(),
+            draw_type: DrawType::Triangles,
+            vertex_buffer: vertex_buffer,
+            ranges: ranges,
+            instances: instances,
+            uniforms: uniforms.clone(),
+            params: params,
+        }
+    };
+    assert_eq!(facade.commands(),
+               vec![draw(vec![0..3, 1..3], 4, params),
+                    Command::Clear {
+                        color: None,
+                        depth: Some(1.0),
+                        stencil: None,
+                    },
+                    draw(vec![0..3], 1, Default::default()),
+                    Command::Finish]);
+}
@@ -0,0 +1,138 @@
+#[macro_use]
+extern crate graphic_backend;
+
+use graphic_backend::*;
+use graphic_backend::software::{Attributes, ShaderEnv};
+use graphic_backend::std140::to_bytes;
+use graphic_backend::testing::headless_opengl;
+
+std140_block! {
+    struct Light {
+        position: [f32; 3],
+        intensity: f32,
+        weights: [Padded<f32>; 2],
+        direction: [f32; 2],
+    }
+}
+
+std140_block! {
+    struct Tint {
+        color: [f32; 4],
+    }
+}
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec2 position;
+    void main() {
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    uniform Tint {
+        vec4 color;
+    };
+    out vec4 out_color;
+    void main() {
+        out_color = color;
+    }
+"#;
+static FS_HALF_SRC: &'static str = r#"
+    #version 150
+    uniform Tint {
+        vec4 color;
+    };
+    out vec4 out_color;
+    void main() {
+        out_color = vec4(color.rgb * 0.5, 1.0);
+    }
+"#;
+
+fn floats(values: &[f32]) -> Vec<u8> {
+    slice_as_bytes(values).to_vec()
+}
+
+#[test]
+fn block_fields_at_std140_offsets() {
+    let light = Light {
+        position: [1.0, 2.0, 3.0],
+        intensity: 4.0,
+        weights: [Padded(5.0), Padded(6.0)],
+        direction: [7.0, 8.0],
+    };
+    let bytes = to_bytes(&light);
+    assert_eq!(<Light as Std140>::SIZE, 64);
+    // array elements take 16 bytes and the block is padded to 16 bytes,
+    // the padding written as zeros
+    let expected = floats(&[1.0, 2.0, 3.0, 4.0,
+                            5.0, 0.0, 0.0, 0.0,
+                            6.0, 0.0, 0.0, 0.0,
+                            7.0, 8.0, 0.0, 0.0]);
+    assert_eq!(bytes, expected);
+}
+
+/// Draws with two programs reading the same buffer, before and after
+/// writing to it.
+fn shared_block<F: Facade>(facade: &F) {
+    use graphic_backend::InputBuffer::*;
+
+    let full = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let half = facade.program(VS_SRC, FS_HALF_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position", Vec2(vec![-1.0, -1.0, 3.0, -1.0, -1.0, 3.0]))
+        .build()
+        .unwrap();
+    let tint = facade.uniform_buffer(&Tint { color: [1.0, 0.0, 1.0, 1.0] }).unwrap();
+    full.bind_uniform_block("Tint", &tint).unwrap();
+    half.bind_uniform_block("Tint", &tint).unwrap();
+
+    let draw = |program: &BProgram| {
+        let mut frame = facade.frame();
+        frame.draw(program,
+                  DrawType::Triangles,
+                  &vb,
+                  &Uniforms::new(),
+                  &Default::default())
+            .unwrap();
+        let pixel = frame.read_pixels().get_pixel(1, 1).data;
+        frame.finish().unwrap();
+        pixel
+    };
+    // GL may round half way values either way
+    let close = |a: [u8; 4], b: [u8; 4]| {
+        a.iter().zip(b.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 1)
+    };
+    assert!(close(draw(&full), [255, 0, 255, 255]));
+    assert!(close(draw(&half), [127, 0, 127, 255]));
+
+    // both programs see writes to the buffer
+    tint.update(&Tint { color: [0.0, 1.0, 0.0, 1.0] }).unwrap();
+    assert!(close(draw(&full), [0, 255, 0, 255]));
+    assert!(close(draw(&half), [0, 127, 0, 255]));
+}
+
+#[test]
+fn block_shared_between_programs_software() {
+    let facade = Software::new(4, 4);
+    let vertex = |attrs: &Attributes, _: &ShaderEnv| {
+        let p = attrs.vec2("position");
+        ([p[0], p[1], 0.0, 1.0], Vec::new())
+    };
+    facade.shader(VS_SRC,
+                  FS_SRC,
+                  vertex,
+                  |_, env| env.block::<Tint>("Tint").unwrap().color);
+    facade.shader(VS_SRC, FS_HALF_SRC, vertex, |_, env| {
+        let c = env.block::<Tint>("Tint").unwrap().color;
+        [c[0] * 0.5, c[1] * 0.5, c[2] * 0.5, 1.0]
+    });
+    shared_block(&facade);
+}
+
+#[test]
+fn block_shared_between_programs_opengl_headless() {
+    if let Some(facade) = headless_opengl(4, 4) {
+        shared_block(&facade);
+    }
+}
@@ -0,0 +1,239 @@
+#[macro_use]
+extern crate graphic_backend;
+
+use graphic_backend::*;
+use graphic_backend::recording::RecVertexBufferBuilder;
+
+static VS_SRC: &'static str = r#"
+    #version 150
+    in vec3 position;
+    in float weight;
+    void main() {
+        gl_Position = vec4(position * weight, 1.0);
+    }
+"#;
+static FS_SRC: &'static str = r#"
+    #version 150
+    struct Light {
+        vec3 color;
+        float intensity;
+    };
+    uniform Light lights[4];
+    uniform float brightness;
+    out vec4 out_color;
+    void main() {
+        out_color = vec4(lights[0].color * brightness, 1.0);
+    }
+"#;
+
+fn input(name: &str, ty: DataType, size: usize) -> ProgramInput {
+    ProgramInput {
+        name: String::from(name),
+        ty: ty,
+        size: size,
+        location: 0,
+    }
+}
+
+#[test]
+fn find_input_matches_arrays_elements_and_members() {
+    let inputs = vec![input("scale", DataType::Float, 1),
+                      input("weights[0]", DataType::Float, 8),
+                      input("lights[0]", DataType::Other, 4),
+                      input("offsets[0]", DataType::Vec2, 2)];
+    let found = |name| find_input(&inputs, name).map(|i| i.name.clone());
+    assert_eq!(found("scale"), Some(String::from("scale")));
+    assert_eq!(found("weights"), Some(String::from("weights[0]")));
+    assert_eq!(found("weights[5]"), Some(String::from("weights[0]")));
+    assert_eq!(found("lights[2].color"), Some(String::from("lights[0]")));
+    assert_eq!(found("lights[2]"), Some(String::from("lights[0]")));
+    // only structs reflected as a whole have members
+    assert_eq!(found("offsets[1].x"), None);
+    assert_eq!(found("scale.x"), None);
+    assert_eq!(found("missing"), None);
+}
+
+#[test]
+fn split_index_of_array_elements() {
+    assert_eq!(split_index("lights[12]"), Some(("lights", 12)));
+    assert_eq!(split_index("lights[2].color"), None);
+    assert_eq!(split_index("lights[x]"), None);
+    assert_eq!(split_index("lights"), None);
+}
+
+#[test]
+fn check_uniforms_rejects_mismatched_types() {
+    let facade = Recorder::new(4, 4);
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let warnings = UniformWarnings::new();
+
+    let uniforms: Uniforms<u32> = uniforms! {
+        brightness: Uniform::Float(1.0),
+        "lights[2].color": Uniform::Vec3(1.0, 0.5, 0.0)
+    };
+    assert_eq!(check_uniforms(&*program, &uniforms, &warnings), Ok(()));
+
+    let uniforms: Uniforms<u32> = uniforms! {
+        brightness: Uniform::Vec2(1.0, 1.0)
+    };
+    assert_eq!(check_uniforms(&*program, &uniforms, &warnings),
+               Err(BackendError::InvalidUniformType {
+                   name: String::from("brightness"),
+                   expected: DataType::Float,
+               }));
+}
+
+#[test]
+fn check_uniforms_records_unused_names_once() {
+    let facade = Recorder::new(4, 4);
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let warnings = UniformWarnings::new();
+    let uniforms: Uniforms<u32> = uniforms! {
+        brightness: Uniform::Float(1.0),
+        contrast: Uniform::Float(1.0)
+    };
+    assert_eq!(check_uniforms(&*program, &uniforms, &warnings), Ok(()));
+    assert_eq!(check_uniforms(&*program, &uniforms, &warnings), Ok(()));
+    assert!(warnings.contains("contrast"));
+    assert!(!warnings.contains("brightness"));
+    assert_eq!(warnings.names(), vec![String::from("contrast")]);
+}
+
+#[test]
+fn programs_report_unused_uniforms_of_draws() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Recorder::new(4, 4);
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let vb = facade.vertex_buffer()
+        .add_input("position", Vec3(vec![0.0; 9]))
+        .add_input("weight", Float(vec![1.0; 3]))
+        .build()
+        .unwrap();
+    let uniforms = uniforms! {
+        contrast: Uniform::Float(1.0),
+        brightness: Uniform::Float(1.0),
+        gamma: Uniform::Float(2.2)
+    };
+    let mut frame = facade.frame();
+    for _ in 0..2 {
+        frame.draw(&program, DrawType::Triangles, &vb, &uniforms, &Default::default()).unwrap();
+    }
+    frame.finish().unwrap();
+    assert_eq!(program.unused_uniforms(),
+               vec![String::from("contrast"), String::from("gamma")]);
+}
+
+#[test]
+fn check_inputs_against_attributes() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Recorder::new(4, 4);
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let check = |builder: RecVertexBufferBuilder| {
+        let vb = builder.build().unwrap();
+        check_inputs(&*program, vb.get_buffers())
+    };
+
+    // inputs the program does not read are fine
+    let complete = facade.vertex_buffer()
+        .add_input("position", Vec3(vec![0.0; 3]))
+        .add_input("weight", Float(vec![1.0]))
+        .add_input("color", Vec4(vec![0.0; 4]));
+    assert_eq!(check(complete), Ok(()));
+
+    let missing = facade.vertex_buffer().add_input("position", Vec3(vec![0.0; 3]));
+    assert_eq!(check(missing),
+               Err(BackendError::InvalidAttribute { name: String::from("weight") }));
+
+    let too_short = facade.vertex_buffer()
+        .add_input("position", Vec2(vec![0.0; 2]))
+        .add_input("weight", Float(vec![1.0]));
+    assert_eq!(check(too_short),
+               Err(BackendError::InvalidAttributeSize {
+                   name: String::from("position"),
+                   expected: 3,
+                   actual: 2,
+               }));
+
+    let integers = facade.vertex_buffer()
+        .add_input("position", Vec3(vec![0.0; 3]))
+        .add_input("weight", Int(1, vec![1]));
+    assert_eq!(check(integers),
+               Err(BackendError::InvalidAttributeType {
+                   name: String::from("weight"),
+                   expected: DataType::Float,
+                   actual: AttributeType::I32,
+               }));
+}
+
+#[test]
+fn component_counts_outside_one_to_four_are_rejected() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Recorder::new(4, 4);
+    let build = |input: InputBuffer| {
+        facade.vertex_buffer().add_input("weight", input).build().err()
+    };
+    assert_eq!(Int(0, vec![1, 2]).len(), 0);
+    assert_eq!(build(Int(0, vec![1, 2])),
+               Some(BackendError::InvalidLayout(String::from("`weight` has 0 components"))));
+    assert_eq!(build(Half(5, vec![0; 5])),
+               Some(BackendError::InvalidLayout(String::from("`weight` has 5 components"))));
+    assert_eq!(build(I16Norm(4, vec![0; 4])), None);
+}
+
+#[test]
+fn indices_are_checked_again_after_resizing() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Recorder::new(4, 4);
+    let program = facade.program(VS_SRC, FS_SRC, None, "out_color").unwrap();
+    let mut vb = facade.vertex_buffer()
+        .add_input("position", Vec3(vec![0.0; 9]))
+        .add_input("weight", Float(vec![1.0; 3]))
+        .indices(IndexBuffer::U8(vec![0, 2, 1, 0xff]))
+        .primitive_restart(true)
+        .build()
+        .unwrap();
+    assert_eq!(vb.max_index(), Some(2));
+
+    let uniforms: Uniforms<u32> = Uniforms::new();
+    let mut frame = facade.frame();
+    assert_eq!(frame.draw(&program, DrawType::TriangleStrip, &vb, &uniforms, &Default::default()),
+               Ok(()));
+    vb.buffer_mut(1).resize(2).unwrap();
+    assert_eq!(frame.draw(&program, DrawType::TriangleStrip, &vb, &uniforms, &Default::default()),
+               Err(BackendError::InvalidIndex {
+                   index: 2,
+                   vertices: 2,
+               }));
+    frame.finish().unwrap();
+}
+
+#[test]
+fn sample_counts_other_than_powers_of_two_are_rejected() {
+    match OpenGLBuilder::new().with_multisampling(3).build() {
+        Err(BackendError::ContextCreation(reason)) => {
+            assert_eq!(reason, "3 samples is not a power of two")
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(..) => panic!("3 samples were accepted"),
+    }
+}
+
+#[test]
+fn writes_past_the_end_of_the_address_space_are_rejected() {
+    use graphic_backend::InputBuffer::*;
+
+    let facade = Recorder::new(4, 4);
+    let mut vb = facade.vertex_buffer()
+        .add_input("position", Vec3(vec![0.0; 9]))
+        .build()
+        .unwrap();
+    assert_eq!(vb.buffer_mut(0).write(usize::MAX - 1, &[0; 4]),
+               Err(BackendError::InvalidBufferSize {
+                   expected: usize::MAX,
+                   actual: 36,
+               }));
+}